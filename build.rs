@@ -1,5 +1,8 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::path::PathBuf;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() {
     // Tell cargo to tell rustc to link the flutter_engine
@@ -33,4 +36,201 @@ fn main() {
     bindings
         .write_to_file(out_path.join("embedder.rs"))
         .expect("Couldn't write bindings!");
+
+    generate_key_maps(&out_path);
+}
+
+/// `keymaps.csv` names the keys after the W3C UI Events spec, which winit
+/// mostly follows.
+const WINIT_KEY_CODE_ALIASES: [(&str, &str); 2] =
+    [("MetaLeft", "SuperLeft"), ("MetaRight", "SuperRight")];
+
+/// keymaps.csv has `Meta_L` and `Meta_R`, but X11 layouts put `Super_L` and
+/// `Super_R` on these keys.
+const GTK_KEYSYM_OVERRIDES: [(&str, u64); 2] = [("SuperLeft", 0xffeb), ("SuperRight", 0xffec)];
+
+/// A key of Flutter and the winit variants that map to it. Rows without a
+/// Flutter id list the winit variants that deliberately stay unmapped.
+struct FlutterKey {
+    id: Option<u64>,
+    winit: Vec<String>,
+}
+
+/// Reads a CSV file with a header line into one map per row. Quotes around
+/// fields are stripped, but they can't contain commas.
+fn read_csv(path: &str) -> Vec<HashMap<String, String>> {
+    println!("cargo:rerun-if-changed={path}");
+    let content = fs::read_to_string(path).unwrap_or_else(|err| panic!("{path}: {err}"));
+    let mut lines = content.lines();
+    let split = |line: &str| -> Vec<String> {
+        line.split(',')
+            .map(|field| field.trim_matches('"').to_owned())
+            .collect()
+    };
+    let header = split(lines.next().unwrap_or_default());
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| header.iter().cloned().zip(split(line)).collect())
+        .collect()
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    if value.is_empty() {
+        None
+    } else if let Some(hex) = value.strip_prefix("0x") {
+        Some(u64::from_str_radix(hex, 16).unwrap_or_else(|err| panic!("{value}: {err}")))
+    } else {
+        Some(value.parse().unwrap_or_else(|err| panic!("{value}: {err}")))
+    }
+}
+
+fn read_flutter_keys(path: &str) -> Vec<FlutterKey> {
+    let keys: Vec<FlutterKey> = read_csv(path)
+        .into_iter()
+        .map(|row| FlutterKey {
+            id: parse_number(&row["Flutter id"]),
+            winit: row["winit"].split_whitespace().map(str::to_owned).collect(),
+        })
+        .collect();
+    let mut ids = HashSet::new();
+    let mut variants = HashSet::new();
+    for key in &keys {
+        if let Some(id) = key.id {
+            assert!(ids.insert(id), "{path}: Flutter id {id:#x} is listed twice");
+        } else {
+            assert!(!key.winit.is_empty(), "{path}: row without any key");
+        }
+        for variant in &key.winit {
+            assert!(
+                variants.insert(variant),
+                "{path}: {variant} is listed twice"
+            );
+        }
+    }
+    keys
+}
+
+/// Writes a function matching every winit variant listed in `keys`.
+fn write_key_map(out: &mut String, name: &str, ty: &str, keys: &[FlutterKey]) {
+    let enum_name = ty.split('<').next().unwrap();
+    writeln!(out, "fn {name}(key: {ty}) -> Option<u64> {{").unwrap();
+    writeln!(out, "    Some(match key {{").unwrap();
+    for key in keys.iter().filter(|key| !key.winit.is_empty()) {
+        let pattern = key
+            .winit
+            .iter()
+            .map(|variant| format!("{enum_name}::{variant}"))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        match key.id {
+            Some(id) => writeln!(out, "        {pattern} => {id:#x},").unwrap(),
+            None => writeln!(out, "        {pattern} => return None,").unwrap(),
+        }
+    }
+    writeln!(out, "        _ => return None,").unwrap();
+    writeln!(out, "    }})").unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+/// Generates the translation tables from winit's keys to the ones of Flutter
+/// and GTK.
+fn generate_key_maps(out_path: &Path) {
+    let keymaps = read_csv("keymaps.csv");
+    let physical_keys = read_flutter_keys("keyboard_physical_keys.csv");
+    let logical_keys = read_flutter_keys("keyboard_logical_keys.csv");
+
+    // The X11 keycode is the evdev one plus 8. The first row of every key is
+    // the canonical one.
+    let mut keymap_rows = HashMap::new();
+    for row in &keymaps {
+        let code = row["HTML code"].as_str();
+        let code = WINIT_KEY_CODE_ALIASES
+            .iter()
+            .find(|(html, _)| *html == code)
+            .map_or(code, |(_, winit)| *winit);
+        keymap_rows.entry(code).or_insert_with(Vec::new).push(row);
+    }
+
+    // Flutter's ids for the keyboard page are the USB HID usages, so they
+    // have to agree with keymaps.csv.
+    for key in &physical_keys {
+        let id = match key.id {
+            Some(id) if id >> 16 == 0x7 => id,
+            _ => continue,
+        };
+        for variant in &key.winit {
+            let usages: Vec<u64> = keymap_rows
+                .get(variant.as_str())
+                .into_iter()
+                .flatten()
+                .filter_map(|row| parse_number(&row["USB Keycodes"]))
+                .collect();
+            assert!(
+                usages.is_empty() || usages.contains(&(id & 0xffff)),
+                "{variant} is {id:#x} in keyboard_physical_keys.csv, but has the USB usages {usages:?} in keymaps.csv"
+            );
+        }
+    }
+
+    let write = |name: &str, out: String| {
+        fs::write(out_path.join(name), out).expect("Couldn't write key map!");
+    };
+    let mut out = String::new();
+    write_key_map(&mut out, "physical_key_id", "KeyCode", &physical_keys);
+    write("keyboard_physical_key_map.rs", out);
+    let mut out = String::new();
+    write_key_map(&mut out, "logical_key_id", "Key<'static>", &logical_keys);
+    write("keyboard_logical_key_map.rs", out);
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "fn gtk_key_codes(code: KeyCode) -> Option<(u64, u64)> {{"
+    )
+    .unwrap();
+    writeln!(out, "    Some(match code {{").unwrap();
+    for variant in physical_keys
+        .iter()
+        .filter(|key| key.id.is_some())
+        .flat_map(|key| &key.winit)
+    {
+        let row = match keymap_rows
+            .get(variant.as_str())
+            .and_then(|rows| rows.iter().find(|row| !row["Linux Keycode"].is_empty()))
+        {
+            Some(row) => row,
+            None => continue,
+        };
+        let keycode = parse_number(&row["Linux Keycode"]).unwrap() + 8;
+        let function_key = variant
+            .strip_prefix('F')
+            .and_then(|n| n.parse::<u64>().ok());
+        let keysym = if let Some(&(_, keysym)) = GTK_KEYSYM_OVERRIDES
+            .iter()
+            .find(|(name, _)| name == variant)
+        {
+            keysym
+        } else if let Some(n @ 13..=24) = function_key {
+            // keymaps.csv lacks the keysyms above F12, but they are
+            // consecutive.
+            0xffbe + n - 1
+        } else {
+            match parse_number(&row["X11 keysym"]).unwrap_or(0) {
+                // GTK reports the keysym of the unshifted letter.
+                keysym @ 0x41..=0x5a => keysym + 0x20,
+                keysym => keysym,
+            }
+        };
+        writeln!(
+            out,
+            "        KeyCode::{variant} => ({keycode:#04x}, {keysym:#06x}),"
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => return None,").unwrap();
+    writeln!(out, "    }})").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    write("keyboard_gtk_key_map.rs", out);
 }
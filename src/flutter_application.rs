@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    f64::consts::FRAC_PI_2,
     ffi::{CStr, CString},
     mem::{size_of, MaybeUninit},
     os::{
@@ -8,7 +9,10 @@ use std::{
     },
     path::{Path, PathBuf},
     ptr::{null, null_mut},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     thread::ThreadId,
     time::Duration,
 };
@@ -16,12 +20,16 @@ use std::{
 use arboard::Clipboard;
 use ash::vk::Handle;
 use log::Level;
-use tokio::runtime::Runtime;
-use wgpu::{Device, Instance, Queue, Surface};
+use tokio::runtime::{Builder, Runtime};
+use wgpu::{Buffer, Device, Instance, Queue, Surface};
+use wgpu_core::device::DeviceError;
 use wgpu_hal::api::Vulkan;
 use winit::{
     dpi::PhysicalPosition,
-    event::{DeviceId, ElementState, KeyEvent, MouseButton, MouseScrollDelta, TouchPhase},
+    event::{
+        DeviceId, ElementState, Force, Ime, KeyEvent, MouseButton, MouseScrollDelta, Touch,
+        TouchPhase,
+    },
     event_loop::EventLoopProxy,
     keyboard::ModifiersState,
     window::{CursorIcon, Window},
@@ -31,18 +39,31 @@ use crate::{
     flutter_application::{mouse_cursor::MouseCursor, platform::Platform, text_input::TextInput},
     flutter_bindings::{
         FlutterCustomTaskRunners, FlutterEngine, FlutterEngineAOTData, FlutterEngineCollectAOTData,
-        FlutterEngineGetCurrentTime, FlutterEngineInitialize, FlutterEngineOnVsync,
-        FlutterEngineResult, FlutterEngineResult_kInternalInconsistency,
-        FlutterEngineResult_kInvalidArguments, FlutterEngineResult_kInvalidLibraryVersion,
-        FlutterEngineResult_kSuccess, FlutterEngineRunInitialized, FlutterEngineRunTask,
-        FlutterEngineScheduleFrame, FlutterEngineSendPlatformMessage,
-        FlutterEngineSendPlatformMessageResponse, FlutterEngineSendPointerEvent,
-        FlutterEngineSendWindowMetricsEvent, FlutterEngineShutdown, FlutterFrameInfo,
-        FlutterPlatformMessage, FlutterPlatformMessageResponseHandle,
-        FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse, FlutterPointerEvent,
-        FlutterPointerPhase, FlutterPointerPhase_kAdd, FlutterPointerPhase_kDown,
-        FlutterPointerPhase_kHover, FlutterPointerPhase_kMove, FlutterPointerPhase_kRemove,
-        FlutterPointerPhase_kUp, FlutterPointerSignalKind_kFlutterPointerSignalKindNone,
+        FlutterEngineDisplay,
+        FlutterEngineDisplaysUpdateType_kFlutterEngineDisplaysUpdateTypeStartup,
+        FlutterEngineGetCurrentTime, FlutterEngineInitialize, FlutterEngineNotifyDisplayUpdate,
+        FlutterEngineOnVsync, FlutterEnginePostCallbackOnAllNativeThreads, FlutterEngineResult,
+        FlutterEngineResult_kInternalInconsistency, FlutterEngineResult_kInvalidArguments,
+        FlutterEngineResult_kInvalidLibraryVersion, FlutterEngineResult_kSuccess,
+        FlutterEngineRunInitialized, FlutterEngineRunTask, FlutterEngineScheduleFrame,
+        FlutterEngineSendPlatformMessage, FlutterEngineSendPlatformMessageResponse,
+        FlutterEngineSendPointerEvent, FlutterEngineSendWindowMetricsEvent, FlutterEngineShutdown,
+        FlutterEngineUpdateLocales, FlutterFrameInfo, FlutterLocale, FlutterNativeThreadType,
+        FlutterNativeThreadType_kFlutterNativeThreadTypeUI, FlutterPlatformMessage,
+        FlutterPlatformMessageResponseHandle, FlutterPointerDeviceKind,
+        FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
+        FlutterPointerDeviceKind_kFlutterPointerDeviceKindStylus,
+        FlutterPointerDeviceKind_kFlutterPointerDeviceKindTrackpad, FlutterPointerEvent,
+        FlutterPointerMouseButtons_kFlutterPointerButtonMouseBack,
+        FlutterPointerMouseButtons_kFlutterPointerButtonMouseForward,
+        FlutterPointerMouseButtons_kFlutterPointerButtonMouseMiddle,
+        FlutterPointerMouseButtons_kFlutterPointerButtonMousePrimary,
+        FlutterPointerMouseButtons_kFlutterPointerButtonMouseSecondary, FlutterPointerPhase,
+        FlutterPointerPhase_kAdd, FlutterPointerPhase_kCancel, FlutterPointerPhase_kDown,
+        FlutterPointerPhase_kHover, FlutterPointerPhase_kMove, FlutterPointerPhase_kPanZoomEnd,
+        FlutterPointerPhase_kPanZoomStart, FlutterPointerPhase_kPanZoomUpdate,
+        FlutterPointerPhase_kRemove, FlutterPointerPhase_kUp,
+        FlutterPointerSignalKind_kFlutterPointerSignalKindNone,
         FlutterPointerSignalKind_kFlutterPointerSignalKindScroll, FlutterProjectArgs,
         FlutterRendererConfig, FlutterRendererConfig__bindgen_ty_1, FlutterRendererType_kVulkan,
         FlutterSemanticsCustomAction, FlutterSemanticsNode, FlutterTask,
@@ -53,35 +74,139 @@ use crate::{
 };
 
 use self::{
-    keyboard::Keyboard, lifecycle::LifecycleState, platform_views::PlatformViewsHandler,
+    damage::DamageTracker,
+    keyboard::Keyboard,
+    lifecycle::LifecycleState,
+    perf_hud::PerfHud,
+    platform_views::{PlatformViewLayer, PlatformViewsHandler},
+    restoration::RestorationHandler,
     task_runner::TaskRunner,
+    touch::Touches,
+    vulkan_sync::VulkanSync,
 };
 
-// mod keyboard_event;
-// use keyboard_event::{FlutterKeyboardEvent, FlutterKeyboardEventType, LinuxToolkit};
 mod compositor;
+mod damage;
 mod keyboard;
+mod keyboard_event;
 mod lifecycle;
 mod message_codec;
 mod mouse_cursor;
+mod perf_hud;
 mod platform;
 mod platform_views;
+mod render_target;
+mod restoration;
 mod task_runner;
 mod text_input;
+mod touch;
+mod vulkan_sync;
 
 use compositor::Compositor;
+pub use platform_views::{
+    FlutterOffset, FlutterSize, PlatformView, PlatformViewPointer, PlatformViewPointerAction,
+    PlatformViewPointerEvent, TextDirection,
+};
+pub use render_target::{PresentationConfig, RenderTarget, SurfaceTarget, TextureTarget};
 
-const PIXELS_PER_LINE: f64 = 10.0;
+/// Touches report a single button while in contact, like the official shells.
+const TOUCH_CONTACT: u64 = 1;
+/// Used when the monitor doesn't report its refresh rate.
+const DEFAULT_REFRESH_RATE_MILLIHERTZ: u32 = 60_000;
+/// How often to check whether the GPU caught up while the engine is
+/// throttled.
+const GPU_POLL_INTERVAL_NANOS: u64 = 1_000_000;
 const FLUTTER_TEXTINPUT_CHANNEL: &str = "flutter/textinput";
+const FLUTTER_KEYEVENT_CHANNEL: &str = "flutter/keyevent";
+const FLUTTER_KEYBOARD_CHANNEL: &str = "flutter/keyboard";
 const FLUTTER_MOUSECURSOR_CHANNEL: &str = "flutter/mousecursor";
 const FLUTTER_PLATFORM_CHANNEL: &str = "flutter/platform";
 const FLUTTER_LIFECYCLE_CHANNEL: &str = "flutter/lifecycle";
 const FLUTTER_PLATFORM_VIEWS_CHANNEL: &str = "flutter/platform_views";
+const FLUTTER_RESTORATION_CHANNEL: &str = "flutter/restoration";
+
+/// Distinguishes engine instances, so callbacks queued by an engine that has
+/// been replaced in the meantime aren't run on its successor.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// How far a mouse wheel scrolls. Pixel deltas from touchpads and smooth
+/// scrolling devices are passed through unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollConfig {
+    /// Logical pixels per line, which is one notch on most wheels. Scaled
+    /// with the scale factor of the window.
+    pub pixels_per_line: f64,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            pixels_per_line: 40.0,
+        }
+    }
+}
 
+#[derive(Clone, Copy)]
 struct PointerState {
     virtual_id: i32,
     position: PhysicalPosition<f64>,
     held_buttons: u64,
+    device_kind: FlutterPointerDeviceKind,
+    /// Normalized to `0.0..=1.0`, `None` if the device doesn't report it.
+    /// Only sent to platform views, the embedder API has no field for it.
+    pressure: Option<f64>,
+    /// Angle between a stylus and the surface normal in radians. Only sent
+    /// to platform views, like `pressure`.
+    tilt: f64,
+    /// Set while a trackpad gesture is in progress.
+    pan_zoom: Option<PanZoomState>,
+}
+
+/// The trackpad gestures making up a pan/zoom. winit reports each of them
+/// with its own phase, while Flutter only knows a single pan/zoom.
+#[derive(Clone, Copy)]
+enum PanZoomGesture {
+    Pan = 1,
+    Magnify = 2,
+    Rotate = 4,
+}
+
+/// Accumulated since the start of the pan/zoom, which is what Flutter
+/// expects in every update.
+#[derive(Clone, Copy)]
+struct PanZoomState {
+    pan: PhysicalPosition<f64>,
+    scale: f64,
+    rotation: f64,
+    /// The [`PanZoomGesture`]s that haven't ended yet.
+    active_gestures: u8,
+}
+
+impl Default for PanZoomState {
+    fn default() -> Self {
+        Self {
+            pan: PhysicalPosition::new(0.0, 0.0),
+            scale: 1.0,
+            rotation: 0.0,
+            active_gestures: 0,
+        }
+    }
+}
+
+impl PointerState {
+    /// winit reports pens as touches. The altitude angle is only set for a
+    /// stylus, which is the only way to tell them apart.
+    fn update_force(&mut self, force: Option<Force>) {
+        if let Some(Force::Calibrated {
+            altitude_angle: Some(altitude_angle),
+            ..
+        }) = force
+        {
+            self.device_kind = FlutterPointerDeviceKind_kFlutterPointerDeviceKindStylus;
+            self.tilt = FRAC_PI_2 - altitude_angle;
+        }
+        self.pressure = force.map(|force| force.normalized());
+    }
 }
 
 struct SendFlutterTask(FlutterTask);
@@ -90,6 +215,12 @@ unsafe impl Send for SendFlutterTask {}
 struct SendFlutterPlatformMessageResponseHandle(*const FlutterPlatformMessageResponseHandle);
 unsafe impl Send for SendFlutterPlatformMessageResponseHandle {}
 
+/// The baton of [`FlutterApplication::after_ui_thread_idle`].
+struct UiThreadIdle {
+    event_loop_proxy: Mutex<EventLoopProxy<FlutterApplicationCallback>>,
+    callback: Mutex<Option<FlutterApplicationCallback>>,
+}
+
 pub type FlutterApplicationCallback =
     Box<dyn FnOnce(&mut FlutterApplication) -> bool + 'static + Send>;
 
@@ -97,11 +228,49 @@ struct FlutterApplicationUserData {
     event_loop_proxy: Mutex<EventLoopProxy<FlutterApplicationCallback>>,
     instance: Arc<Instance>,
     runtime: Arc<Runtime>,
+    /// Declared before `device`, so it's dropped first.
+    vulkan_sync: Mutex<VulkanSync>,
     device: Device,
-    surface: Surface,
+    render_target: RenderTarget,
     queue: Queue,
     main_thread: ThreadId,
     render_task_runner: TaskRunner,
+    platform_view_layers: Mutex<Vec<PlatformViewLayer>>,
+    /// The duration of a single frame on the current display.
+    frame_interval_nanos: AtomicU64,
+    /// When the last frame was handed to the presentation engine, 0 before the
+    /// first frame.
+    last_present_nanos: AtomicU64,
+    presentation: PresentationConfig,
+    /// Number of presented frames the GPU hasn't finished yet.
+    frames_in_flight: Arc<AtomicU32>,
+    perf_hud: Arc<PerfHud>,
+    damage: Mutex<DamageTracker>,
+    /// Zero-initialized and never written to, so areas can be cleared by
+    /// copying from it. Grows to the largest area cleared so far, the size
+    /// is kept alongside.
+    zero_buffer: Mutex<Option<(Buffer, u64)>>,
+    generation: u64,
+    /// Set when wgpu reports that the device was lost. All GPU work is
+    /// skipped from then on, the application has to be recreated.
+    device_lost: Arc<AtomicBool>,
+}
+
+impl FlutterApplicationUserData {
+    /// Wraps `callback` for sending it to the platform thread.
+    fn callback(
+        &self,
+        callback: impl FnOnce(&mut FlutterApplication) -> bool + 'static + Send,
+    ) -> FlutterApplicationCallback {
+        let generation = self.generation;
+        Box::new(move |application| {
+            if application.user_data.generation != generation {
+                log::debug!("Dropping callback of a previous engine instance");
+                return false;
+            }
+            callback(application)
+        })
+    }
 }
 
 pub struct FlutterApplication {
@@ -110,22 +279,37 @@ pub struct FlutterApplication {
     instance: Arc<Instance>,
     aot_data: Vec<FlutterEngineAOTData>,
     mice: HashMap<DeviceId, PointerState>,
-    current_mouse_id: i32,
+    touches: Touches,
+    /// The next virtual device id, shared by all kinds of pointers.
+    current_pointer_id: i32,
+    /// Pointer events of the current event loop iteration, in order.
+    pointer_events: Vec<FlutterPointerEvent>,
     runtime: Arc<Runtime>,
     clipboard: Arc<Mutex<Clipboard>>,
     keyboard: Keyboard,
+    scroll_config: ScrollConfig,
     window: Arc<Window>,
     platform_views_handler: PlatformViewsHandler,
+    /// The refresh rate last announced to the engine.
+    refresh_rate_millihertz: Option<u32>,
+    restoration_handler: RestorationHandler,
     user_data: Box<FlutterApplicationUserData>,
     set_cursor_icon: Box<dyn Fn(Option<CursorIcon>) + 'static>,
 }
 
 impl FlutterApplication {
+    /// Builds a runtime suitable for [`Self::new`]. Vsync and delayed
+    /// platform tasks are answered from it with tokio's timers.
+    pub fn create_runtime() -> std::io::Result<Arc<Runtime>> {
+        Ok(Arc::new(Builder::new_multi_thread().enable_all().build()?))
+    }
+
     pub fn new(
         runtime: Arc<Runtime>,
         asset_bundle_path: &Path,
         flutter_flags: Vec<String>,
-        surface: Surface,
+        render_target: RenderTarget,
+        presentation: PresentationConfig,
         instance: Arc<Instance>,
         device: Device,
         queue: Queue,
@@ -216,15 +400,53 @@ impl FlutterApplication {
             .map(|arg| arg.as_bytes().as_ptr() as _)
             .collect();
 
+        let device_lost = Arc::new(AtomicBool::new(false));
+        {
+            let device_lost = device_lost.clone();
+            let event_loop_proxy = Mutex::new(event_loop_proxy.clone());
+            device.on_uncaptured_error(move |error| {
+                if !Self::is_device_lost_error(&error) {
+                    panic!("wgpu error: {error}");
+                }
+                if !device_lost.swap(true, Ordering::AcqRel) {
+                    log::error!("Graphics device lost: {error}");
+                    // Wake up the event loop, so the host notices.
+                    event_loop_proxy
+                        .lock()
+                        .unwrap()
+                        .send_event(Box::new(|_| false))
+                        .ok();
+                }
+            });
+        }
+
+        let perf_hud = Arc::new(PerfHud::new(false));
+        let render_perf_hud = perf_hud.clone();
+
         let user_data = Box::new(FlutterApplicationUserData {
             event_loop_proxy: Mutex::new(event_loop_proxy),
             instance: instance.clone(),
             runtime: runtime.clone(),
+            vulkan_sync: Mutex::new(VulkanSync::new(&device, presentation.frames_in_flight)),
             device,
-            surface,
+            render_target,
             queue,
             main_thread: std::thread::current().id(),
-            render_task_runner: TaskRunner::new("renderer".to_owned()),
+            render_task_runner: TaskRunner::new("renderer".to_owned(), move || {
+                render_perf_hud.render_task_started()
+            }),
+            platform_view_layers: Default::default(),
+            frame_interval_nanos: AtomicU64::new(Self::frame_interval_nanos(
+                DEFAULT_REFRESH_RATE_MILLIHERTZ,
+            )),
+            last_present_nanos: AtomicU64::new(0),
+            presentation,
+            frames_in_flight: Default::default(),
+            perf_hud,
+            damage: Default::default(),
+            zero_buffer: Default::default(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            device_lost,
         });
 
         let clipboard = Arc::new(Mutex::new(Clipboard::new().unwrap()));
@@ -235,11 +457,16 @@ impl FlutterApplication {
             instance,
             aot_data: vec![],
             mice: Default::default(),
-            current_mouse_id: 0,
+            touches: Default::default(),
+            current_pointer_id: 0,
+            pointer_events: vec![],
             runtime,
             keyboard: Keyboard::new(clipboard.clone()),
+            scroll_config: Default::default(),
             clipboard,
             platform_views_handler: Default::default(),
+            refresh_rate_millihertz: None,
+            restoration_handler: Default::default(),
             user_data,
             window,
             set_cursor_icon: Box::new(set_cursor_icon),
@@ -321,24 +548,61 @@ impl FlutterApplication {
         Self::unwrap_result(unsafe { FlutterEngineRunInitialized(self.engine) });
     }
 
-    pub fn metrics_changed(&self, width: u32, height: u32, pixel_ratio: f64, x: i32, y: i32) {
+    fn frame_interval_nanos(refresh_rate_millihertz: u32) -> u64 {
+        1_000_000_000_000 / refresh_rate_millihertz.max(1) as u64
+    }
+
+    /// Picks up the refresh rate of the monitor the window is currently on.
+    /// Needs to be called whenever the window might have moved to a different
+    /// monitor.
+    pub fn display_changed(&mut self) {
+        let refresh_rate_millihertz = self
+            .window
+            .current_monitor()
+            .and_then(|monitor| monitor.refresh_rate_millihertz())
+            .unwrap_or(DEFAULT_REFRESH_RATE_MILLIHERTZ);
+        if self.refresh_rate_millihertz == Some(refresh_rate_millihertz) {
+            return;
+        }
+        self.refresh_rate_millihertz = Some(refresh_rate_millihertz);
+        self.user_data.frame_interval_nanos.store(
+            Self::frame_interval_nanos(refresh_rate_millihertz),
+            Ordering::Relaxed,
+        );
+        log::debug!(
+            "Display refresh rate is {}Hz",
+            refresh_rate_millihertz as f64 / 1000.0
+        );
+
+        let display = FlutterEngineDisplay {
+            struct_size: size_of::<FlutterEngineDisplay>() as _,
+            display_id: 0,
+            single_display: true,
+            refresh_rate: refresh_rate_millihertz as f64 / 1000.0,
+        };
+        Self::unwrap_result(unsafe {
+            FlutterEngineNotifyDisplayUpdate(
+                self.engine,
+                FlutterEngineDisplaysUpdateType_kFlutterEngineDisplaysUpdateTypeStartup,
+                &display,
+                1,
+            )
+        });
+    }
+
+    /// `width` and `height` are in physical pixels, which is also the space
+    /// pointer coordinates are in. The view always starts in the top left
+    /// corner of the window. A surface render target is resized along.
+    pub fn metrics_changed(&self, width: u32, height: u32, pixel_ratio: f64) {
+        if let RenderTarget::Surface(target) = &self.user_data.render_target {
+            target.resize(width, height);
+        }
         self.user_data
             .event_loop_proxy
             .lock()
             .unwrap()
-            .send_event(Box::new(move |application| {
-                let metrics = FlutterWindowMetricsEvent {
-                    struct_size: size_of::<FlutterWindowMetricsEvent>() as _,
-                    width: width as _,
-                    height: height as _,
-                    pixel_ratio,
-                    left: x.max(0) as _,
-                    top: y.max(0) as _,
-                    physical_view_inset_top: 0.0,
-                    physical_view_inset_right: 0.0,
-                    physical_view_inset_bottom: 0.0,
-                    physical_view_inset_left: 0.0,
-                };
+            .send_event(self.user_data.callback(move |application| {
+                let metrics = Self::window_metrics(width, height, pixel_ratio);
                 log::debug!("setting metrics to {metrics:?}");
                 Self::unwrap_result(unsafe {
                     FlutterEngineSendWindowMetricsEvent(application.engine, &metrics)
@@ -350,16 +614,80 @@ impl FlutterApplication {
             .unwrap();
     }
 
+    fn window_metrics(width: u32, height: u32, pixel_ratio: f64) -> FlutterWindowMetricsEvent {
+        FlutterWindowMetricsEvent {
+            struct_size: size_of::<FlutterWindowMetricsEvent>() as _,
+            width: width as _,
+            height: height as _,
+            pixel_ratio,
+            left: 0,
+            top: 0,
+            physical_view_inset_top: 0.0,
+            physical_view_inset_right: 0.0,
+            physical_view_inset_bottom: 0.0,
+            physical_view_inset_left: 0.0,
+        }
+    }
+
+    /// Tells the engine which locales the user prefers, most preferred first.
+    /// Locales are given as BCP 47 language tags like `en-US` or `zh-Hant-TW`.
+    pub fn update_locales(&self, locales: &[&str]) {
+        let locales: Vec<[Option<CString>; 3]> = locales
+            .iter()
+            .map(|locale| {
+                let mut subtags = locale.split(['-', '_']);
+                let language = subtags
+                    .next()
+                    .map(|language| CString::new(language).unwrap());
+                let mut script = None;
+                let mut country = None;
+                for subtag in subtags {
+                    if subtag.len() == 4 && script.is_none() && country.is_none() {
+                        script = Some(CString::new(subtag).unwrap());
+                    } else if country.is_none() {
+                        country = Some(CString::new(subtag).unwrap());
+                    }
+                }
+                [language, country, script]
+            })
+            .collect();
+        let flutter_locales: Vec<FlutterLocale> = locales
+            .iter()
+            .map(|[language, country, script]| FlutterLocale {
+                struct_size: size_of::<FlutterLocale>() as _,
+                language_code: language.as_ref().map_or(null(), |s| s.as_ptr()),
+                country_code: country.as_ref().map_or(null(), |s| s.as_ptr()),
+                script_code: script.as_ref().map_or(null(), |s| s.as_ptr()),
+                variant_code: null(),
+            })
+            .collect();
+        let flutter_locale_ptrs: Vec<*const FlutterLocale> = flutter_locales
+            .iter()
+            .map(|locale| locale as *const _)
+            .collect();
+        Self::unwrap_result(unsafe {
+            FlutterEngineUpdateLocales(
+                self.engine,
+                flutter_locale_ptrs.as_ptr() as _,
+                flutter_locale_ptrs.len() as _,
+            )
+        });
+    }
+
     fn get_mouse(&mut self, device_id: DeviceId) -> &mut PointerState {
         if !self.mice.contains_key(&device_id) {
-            let virtual_id = self.current_mouse_id;
-            self.current_mouse_id += 1;
+            let virtual_id = self.current_pointer_id;
+            self.current_pointer_id += 1;
             self.mice.insert(
                 device_id,
                 PointerState {
                     virtual_id,
                     position: PhysicalPosition::new(0.0, 0.0),
                     held_buttons: 0,
+                    device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
+                    pressure: None,
+                    tilt: 0.0,
+                    pan_zoom: None,
                 },
             );
             self.send_pointer_event(device_id, FlutterPointerPhase_kAdd, None);
@@ -369,36 +697,44 @@ impl FlutterApplication {
 
     pub fn mouse_buttons(&mut self, device_id: DeviceId, state: ElementState, button: MouseButton) {
         let mouse = self.get_mouse(device_id);
-        let old_buttons_held = mouse.held_buttons != 0;
         let button_idx = match button {
-            MouseButton::Left => 1,
-            MouseButton::Right => 2,
-            MouseButton::Middle => 4,
-            MouseButton::Other(x) => 1 << x,
-        };
+            MouseButton::Left => FlutterPointerMouseButtons_kFlutterPointerButtonMousePrimary,
+            MouseButton::Right => FlutterPointerMouseButtons_kFlutterPointerButtonMouseSecondary,
+            MouseButton::Middle => FlutterPointerMouseButtons_kFlutterPointerButtonMouseMiddle,
+            // winit passes through the X11 button numbers. 4 to 7 are the
+            // scroll wheels, so the sixth physical button is number 10.
+            MouseButton::Other(8) => FlutterPointerMouseButtons_kFlutterPointerButtonMouseBack,
+            MouseButton::Other(9) => FlutterPointerMouseButtons_kFlutterPointerButtonMouseForward,
+            MouseButton::Other(x) if (10..32 + 5).contains(&x) => 1 << (x - 5),
+            MouseButton::Other(_) => return,
+        } as u64;
+        let old_held_buttons = mouse.held_buttons;
         match state {
-            ElementState::Pressed => mouse.held_buttons ^= button_idx,
+            ElementState::Pressed => mouse.held_buttons |= button_idx,
             ElementState::Released => mouse.held_buttons &= !button_idx,
         }
+        if mouse.held_buttons == old_held_buttons {
+            // Repeated press or release of the same button.
+            return;
+        }
+        let old_buttons_held = old_held_buttons != 0;
         let new_buttons_held = mouse.held_buttons != 0;
 
-        self.send_pointer_event(
-            device_id,
-            if state == ElementState::Pressed {
-                if old_buttons_held {
-                    FlutterPointerPhase_kMove
-                } else {
-                    FlutterPointerPhase_kDown
-                }
+        let phase = if state == ElementState::Pressed {
+            if old_buttons_held {
+                FlutterPointerPhase_kMove
             } else {
-                if new_buttons_held {
-                    FlutterPointerPhase_kMove
-                } else {
-                    FlutterPointerPhase_kUp
-                }
-            },
-            None,
-        );
+                FlutterPointerPhase_kDown
+            }
+        } else {
+            if new_buttons_held {
+                FlutterPointerPhase_kMove
+            } else {
+                FlutterPointerPhase_kUp
+            }
+        };
+        self.send_pointer_event(device_id, phase, None);
+        self.send_to_platform_views(device_id, phase);
     }
 
     pub fn mouse_entered(&mut self, device_id: DeviceId) {
@@ -406,6 +742,7 @@ impl FlutterApplication {
     }
 
     pub fn mouse_left(&mut self, device_id: DeviceId) {
+        self.send_to_platform_views(device_id, FlutterPointerPhase_kRemove);
         self.send_pointer_event(device_id, FlutterPointerPhase_kRemove, None);
         self.mice.remove(&device_id);
     }
@@ -414,25 +751,36 @@ impl FlutterApplication {
         let mouse = self.get_mouse(device_id);
         mouse.position = position;
         let buttons = mouse.held_buttons;
-        self.send_pointer_event(
-            device_id,
-            if buttons == 0 {
-                FlutterPointerPhase_kHover
-            } else {
-                FlutterPointerPhase_kMove
-            },
-            None,
-        );
+        let phase = if buttons == 0 {
+            FlutterPointerPhase_kHover
+        } else {
+            FlutterPointerPhase_kMove
+        };
+        self.send_pointer_event(device_id, phase, None);
+        self.send_to_platform_views(device_id, phase);
     }
 
-    pub fn mouse_wheel(
-        &mut self,
-        device_id: DeviceId,
-        delta: MouseScrollDelta,
-        _phase: TouchPhase,
-    ) {
+    pub fn mouse_wheel(&mut self, device_id: DeviceId, delta: MouseScrollDelta, phase: TouchPhase) {
         let mouse = self.get_mouse(device_id);
+        // Only trackpads report pixel deltas with meaningful phases, wheels
+        // keep sending discrete scroll signals.
+        if let MouseScrollDelta::PixelDelta(delta) = delta {
+            if phase == TouchPhase::Started || mouse.pan_zoom.is_some() {
+                self.pan_zoom(device_id, PanZoomGesture::Pan, phase, |state| {
+                    state.pan.x += delta.x;
+                    state.pan.y += delta.y;
+                });
+                return;
+            }
+        }
         let buttons = mouse.held_buttons;
+        // Like GTK, Shift turns a vertical wheel into a horizontal one.
+        let delta = match delta {
+            MouseScrollDelta::LineDelta(x, y) if x == 0.0 && self.keyboard.shift_pressed() => {
+                MouseScrollDelta::LineDelta(y, 0.0)
+            }
+            delta => delta,
+        };
         self.send_pointer_event(
             device_id,
             if buttons == 0 {
@@ -444,69 +792,280 @@ impl FlutterApplication {
         )
     }
 
+    /// `delta` is the change of the scale factor.
+    pub fn touchpad_magnify(&mut self, device_id: DeviceId, delta: f64, phase: TouchPhase) {
+        self.pan_zoom(device_id, PanZoomGesture::Magnify, phase, |state| {
+            state.scale *= 1.0 + delta;
+        });
+    }
+
+    /// `delta` is in degrees counterclockwise, while Flutter expects radians
+    /// clockwise.
+    pub fn touchpad_rotate(&mut self, device_id: DeviceId, delta: f32, phase: TouchPhase) {
+        self.pan_zoom(device_id, PanZoomGesture::Rotate, phase, |state| {
+            state.rotation -= (delta as f64).to_radians();
+        });
+    }
+
+    /// Starts the pan/zoom with the first gesture and ends it with the last
+    /// one, sending updates in between.
+    fn pan_zoom(
+        &mut self,
+        device_id: DeviceId,
+        gesture: PanZoomGesture,
+        phase: TouchPhase,
+        update: impl FnOnce(&mut PanZoomState),
+    ) {
+        let mouse = self.get_mouse(device_id);
+        if phase == TouchPhase::Started && mouse.pan_zoom.is_none() {
+            mouse.pan_zoom = Some(PanZoomState::default());
+            self.send_pointer_event(device_id, FlutterPointerPhase_kPanZoomStart, None);
+        }
+        let mouse = self.get_mouse(device_id);
+        let state = match &mut mouse.pan_zoom {
+            Some(state) => state,
+            // The gesture started before the pointer entered the window.
+            None => return,
+        };
+        update(state);
+        match phase {
+            TouchPhase::Started => state.active_gestures |= gesture as u8,
+            TouchPhase::Moved => {}
+            TouchPhase::Ended | TouchPhase::Cancelled => state.active_gestures &= !(gesture as u8),
+        }
+        if state.active_gestures == 0 {
+            self.send_pointer_event(device_id, FlutterPointerPhase_kPanZoomEnd, None);
+            self.get_mouse(device_id).pan_zoom = None;
+        } else {
+            self.send_pointer_event(device_id, FlutterPointerPhase_kPanZoomUpdate, None);
+        }
+    }
+
+    /// Every finger gets its own virtual device, see [`Touches`].
+    pub fn touch(&mut self, touch: Touch) {
+        for (pointer, phase) in self.touches.update(&touch, &mut self.current_pointer_id) {
+            self.send_flutter_pointer_event(&pointer, phase, None);
+            // Platform views track their own contacts, the virtual devices
+            // are a framework concept.
+            if phase != FlutterPointerPhase_kAdd && phase != FlutterPointerPhase_kRemove {
+                self.send_pointer_to_platform_views(pointer, phase);
+            }
+        }
+    }
+
     fn send_pointer_event(
-        &self,
+        &mut self,
         device_id: DeviceId,
         phase: FlutterPointerPhase,
         scroll_delta: Option<MouseScrollDelta>,
     ) {
-        if let Some(mouse) = self.mice.get(&device_id) {
-            let scroll_delta_px = {
-                match scroll_delta {
-                    Some(MouseScrollDelta::LineDelta(x, y)) => PhysicalPosition::new(
-                        (x as f64) * PIXELS_PER_LINE,
-                        (y as f64) * PIXELS_PER_LINE,
-                    ),
-                    Some(MouseScrollDelta::PixelDelta(pt)) => pt,
-                    None => PhysicalPosition::new(0.0, 0.0),
-                }
-            };
-            let event = FlutterPointerEvent {
-                struct_size: size_of::<FlutterPointerEvent>() as _,
-                phase,
-                timestamp: Self::current_time(),
-                x: mouse.position.x,
-                y: mouse.position.y,
-                device: mouse.virtual_id,
-                signal_kind: if scroll_delta.is_none() {
-                    FlutterPointerSignalKind_kFlutterPointerSignalKindNone
-                } else {
-                    FlutterPointerSignalKind_kFlutterPointerSignalKindScroll
-                },
-                scroll_delta_x: scroll_delta_px.x,
-                scroll_delta_y: scroll_delta_px.y,
-                device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
-                buttons: mouse.held_buttons as _,
-                pan_x: 0.0,
-                pan_y: 0.0,
-                scale: 1.0,
-                rotation: 0.0,
-            };
-            self.user_data
+        if let Some(&mouse) = self.mice.get(&device_id) {
+            self.send_flutter_pointer_event(&mouse, phase, scroll_delta);
+        }
+    }
+
+    /// Queues the event until [`Self::flush_pointer_events`].
+    fn send_flutter_pointer_event(
+        &mut self,
+        pointer: &PointerState,
+        phase: FlutterPointerPhase,
+        scroll_delta: Option<MouseScrollDelta>,
+    ) {
+        let pixels_per_line = self.scroll_config.pixels_per_line * self.window.scale_factor();
+        let event = Self::flutter_pointer_event(
+            pointer,
+            phase,
+            scroll_delta,
+            pixels_per_line,
+            Self::current_time(),
+        );
+        self.pointer_events.push(event);
+    }
+
+    /// Positions and pixel deltas are passed on in physical pixels, as the
+    /// embedder API expects them. Only line deltas are converted, with
+    /// `pixels_per_line` already scaled to the window.
+    fn flutter_pointer_event(
+        pointer: &PointerState,
+        phase: FlutterPointerPhase,
+        scroll_delta: Option<MouseScrollDelta>,
+        pixels_per_line: f64,
+        timestamp: u64,
+    ) -> FlutterPointerEvent {
+        let scroll_delta_px = match scroll_delta {
+            Some(MouseScrollDelta::LineDelta(x, y)) => {
+                PhysicalPosition::new(x as f64 * pixels_per_line, y as f64 * pixels_per_line)
+            }
+            Some(MouseScrollDelta::PixelDelta(pt)) => pt,
+            None => PhysicalPosition::new(0.0, 0.0),
+        };
+        let pan_zoom = pointer.pan_zoom.unwrap_or_default();
+        let is_pan_zoom = phase == FlutterPointerPhase_kPanZoomStart
+            || phase == FlutterPointerPhase_kPanZoomUpdate
+            || phase == FlutterPointerPhase_kPanZoomEnd;
+        // The embedder API has no fields for pressure or tilt, so the
+        // framework only sees a stylus as being in contact or not.
+        FlutterPointerEvent {
+            struct_size: size_of::<FlutterPointerEvent>() as _,
+            phase,
+            timestamp,
+            x: pointer.position.x,
+            y: pointer.position.y,
+            device: pointer.virtual_id,
+            signal_kind: if scroll_delta.is_none() {
+                FlutterPointerSignalKind_kFlutterPointerSignalKindNone
+            } else {
+                FlutterPointerSignalKind_kFlutterPointerSignalKindScroll
+            },
+            scroll_delta_x: scroll_delta_px.x,
+            scroll_delta_y: scroll_delta_px.y,
+            device_kind: if is_pan_zoom {
+                FlutterPointerDeviceKind_kFlutterPointerDeviceKindTrackpad
+            } else {
+                pointer.device_kind
+            },
+            buttons: pointer.held_buttons as _,
+            pan_x: pan_zoom.pan.x,
+            pan_y: pan_zoom.pan.y,
+            scale: pan_zoom.scale,
+            rotation: pan_zoom.rotation,
+        }
+    }
+
+    /// Sends all pointer events queued since the last call to the engine at
+    /// once. High polling rate mice report far more often than the display
+    /// refreshes, so this is called once per event loop iteration instead of
+    /// for every event. Keyboard input flushes them as well, so the
+    /// framework sees a click before the keys typed after it.
+    pub fn flush_pointer_events(&mut self) {
+        if self.pointer_events.is_empty() {
+            return;
+        }
+        Self::unwrap_result(unsafe {
+            FlutterEngineSendPointerEvent(
+                self.engine,
+                self.pointer_events.as_ptr(),
+                self.pointer_events.len() as _,
+            )
+        });
+        self.user_data
+            .perf_hud
+            .pointer_events_sent(self.pointer_events.len());
+        self.pointer_events.clear();
+    }
+
+    /// Calls `callback` on the platform thread once the UI thread went idle,
+    /// so everything sent to the engine before has been handled by the
+    /// framework.
+    pub fn after_ui_thread_idle(
+        &self,
+        callback: impl FnOnce(&mut FlutterApplication) -> bool + 'static + Send,
+    ) {
+        // Called on every thread of the engine, including workers that might
+        // outlive it, so the baton is leaked.
+        let baton: &'static UiThreadIdle = Box::leak(Box::new(UiThreadIdle {
+            event_loop_proxy: Mutex::new(self.user_data.event_loop_proxy.lock().unwrap().clone()),
+            callback: Mutex::new(Some(self.user_data.callback(callback))),
+        }));
+        Self::unwrap_result(unsafe {
+            FlutterEnginePostCallbackOnAllNativeThreads(
+                self.engine,
+                Some(Self::native_thread_callback),
+                baton as *const UiThreadIdle as _,
+            )
+        });
+    }
+
+    extern "C" fn native_thread_callback(type_: FlutterNativeThreadType, user_data: *mut c_void) {
+        if type_ != FlutterNativeThreadType_kFlutterNativeThreadTypeUI {
+            return;
+        }
+        let baton = unsafe { &*(user_data as *const UiThreadIdle) };
+        if let Some(callback) = baton.callback.lock().unwrap().take() {
+            baton
                 .event_loop_proxy
                 .lock()
                 .unwrap()
-                .send_event(Box::new(move |application| {
-                    Self::unwrap_result(unsafe {
-                        FlutterEngineSendPointerEvent(application.engine, &event, 1)
-                    });
-                    drop(event);
-                    false
-                }))
-                .ok()
-                .unwrap();
+                .send_event(callback)
+                .ok();
+        }
+    }
+
+    /// Platform views only see pointer events in addition to the engine, since
+    /// the framework needs all of them to resolve the gesture arena.
+    fn send_to_platform_views(&mut self, device_id: DeviceId, phase: FlutterPointerPhase) {
+        if let Some(&mouse) = self.mice.get(&device_id) {
+            self.send_pointer_to_platform_views(mouse, phase);
         }
     }
 
+    fn send_pointer_to_platform_views(
+        &mut self,
+        pointer: PointerState,
+        phase: FlutterPointerPhase,
+    ) {
+        let action = match phase {
+            x if x == FlutterPointerPhase_kDown => PlatformViewPointerAction::Down,
+            x if x == FlutterPointerPhase_kUp => PlatformViewPointerAction::Up,
+            x if x == FlutterPointerPhase_kMove => PlatformViewPointerAction::Move,
+            x if x == FlutterPointerPhase_kHover => PlatformViewPointerAction::Hover,
+            x if x == FlutterPointerPhase_kRemove || x == FlutterPointerPhase_kCancel => {
+                PlatformViewPointerAction::Cancel
+            }
+            _ => return,
+        };
+        let layers = self.user_data.platform_view_layers.lock().unwrap();
+        self.platform_views_handler
+            .pointer_event(self.engine, &layers, &pointer, action);
+    }
+
     pub fn modifiers_changed(&mut self, state: ModifiersState) {
         self.keyboard.modifiers_changed(state);
     }
 
+    /// Brings the framework's idea of which modifiers are held in line with
+    /// the last [`Self::modifiers_changed`]. Call it once all window events
+    /// of an event loop iteration have been handled.
+    pub fn sync_modifiers(&mut self) {
+        self.flush_pointer_events();
+        self.keyboard.sync_modifiers(self.engine);
+    }
+
     pub fn key_event(&mut self, _device_id: DeviceId, event: KeyEvent, synthesized: bool) {
-        self.keyboard.key_event(self.engine, event, synthesized);
+        self.flush_pointer_events();
+        if self.platform_views_handler.key_event(&event) {
+            return;
+        }
+        self.keyboard
+            .key_event(self.engine, &self.user_data, event, synthesized);
+    }
+
+    pub fn ime(&mut self, ime: Ime) {
+        self.flush_pointer_events();
+        self.keyboard.ime_event(self.engine, &self.window, ime);
     }
 
     pub fn focused(&mut self, focused: bool) {
+        if focused {
+            self.keyboard.focus_gained();
+        } else {
+            // The keys released on focus loss came after everything queued.
+            self.flush_pointer_events();
+            self.keyboard.focus_lost(self.engine);
+            // The button releases will go to whichever window has the focus
+            // now, so end the gestures here.
+            let held: Vec<DeviceId> = self
+                .mice
+                .iter()
+                .filter(|(_, mouse)| mouse.held_buttons != 0)
+                .map(|(&device_id, _)| device_id)
+                .collect();
+            for device_id in held {
+                self.get_mouse(device_id).held_buttons = 0;
+                self.send_pointer_event(device_id, FlutterPointerPhase_kCancel, None);
+                self.send_to_platform_views(device_id, FlutterPointerPhase_kCancel);
+            }
+        }
         let channel = CString::new(FLUTTER_LIFECYCLE_CHANNEL).unwrap();
         let lifecycle = serde_variant::to_variant_name(if focused {
             &LifecycleState::Resumed
@@ -527,12 +1086,41 @@ impl FlutterApplication {
         drop(channel);
     }
 
+    /// Shows frame timings and resource usage in the top left corner.
+    pub fn set_perf_hud_enabled(&self, enabled: bool) {
+        self.user_data.perf_hud.set_enabled(enabled);
+        self.schedule_frame();
+    }
+
+    pub fn set_scroll_config(&mut self, config: ScrollConfig) {
+        self.scroll_config = config;
+    }
+
+    pub fn toggle_perf_hud(&self) {
+        self.set_perf_hud_enabled(!self.user_data.perf_hud.is_enabled());
+    }
+
     pub fn schedule_frame(&self) {
         Self::unwrap_result(unsafe { FlutterEngineScheduleFrame(self.engine) });
     }
 
-    pub fn surface(&self) -> &Surface {
-        &self.user_data.surface
+    /// Registers a factory for platform views of the given type. The
+    /// framework refers to it as `viewType` when creating a view.
+    pub fn register_platform_view_type(
+        &mut self,
+        view_type: &str,
+        factory: impl Fn(i32, Option<serde_json::Value>) -> Box<dyn PlatformView> + 'static,
+    ) {
+        self.platform_views_handler
+            .register_view_type(view_type.to_owned(), Box::new(factory));
+    }
+
+    /// Only available when presenting to a window surface.
+    pub fn surface(&self) -> Option<&Surface> {
+        self.user_data.render_target.surface()
+    }
+    pub fn render_target(&self) -> &RenderTarget {
+        &self.user_data.render_target
     }
     pub fn instance(&self) -> &Instance {
         &self.instance
@@ -544,6 +1132,34 @@ impl FlutterApplication {
         &self.user_data.queue
     }
 
+    /// The state the framework asked to persist through the restoration
+    /// channel, to be handed to [`Self::set_restoration_data`] of a new
+    /// instance when restarting the engine.
+    pub fn restoration_data(&self) -> Option<Vec<u8>> {
+        self.restoration_handler.data().map(|data| data.to_vec())
+    }
+
+    /// Must be called before [`Self::run`], since the framework only asks
+    /// for the data once at startup.
+    pub fn set_restoration_data(&mut self, data: Option<Vec<u8>>) {
+        self.restoration_handler = RestorationHandler::new(data);
+    }
+
+    /// If this returns `true`, the application can't render anymore and has
+    /// to be replaced by a new one with a new device.
+    pub fn is_device_lost(&self) -> bool {
+        self.user_data.device_lost.load(Ordering::Acquire)
+    }
+
+    /// When the compositor last presented a frame, in the same time base as
+    /// [`Self::current_time`]. `None` until the first frame is out.
+    pub fn last_present_time(&self) -> Option<u64> {
+        match self.user_data.last_present_nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(nanos),
+        }
+    }
+
     pub fn current_time() -> u64 {
         unsafe { FlutterEngineGetCurrentTime() }
     }
@@ -561,13 +1177,14 @@ impl FlutterApplication {
         let data =
             unsafe { std::slice::from_raw_parts(message.message, message.message_size as _) }
                 .to_vec();
-        user_data.event_loop_proxy.lock().unwrap().send_event(Box::new(move |this| {
+        user_data.event_loop_proxy.lock().unwrap().send_event(user_data.callback(move |this| {
             if let Ok(channel) = channel {
                 log::debug!("Platform message on channel {channel}.");
                 let mut response = None;
                 if channel == FLUTTER_TEXTINPUT_CHANNEL {
                     if let Ok(text_input) = serde_json::from_slice::<TextInput>(&data) {
-                        this.keyboard.handle_textinput_message(text_input);
+                        this.keyboard
+                            .handle_textinput_message(&this.window, text_input);
                     } else {
                         log::debug!("Unknown textinput message: {:?}", std::str::from_utf8(&data));
                     }
@@ -583,10 +1200,32 @@ impl FlutterApplication {
                     } else {
                         log::error!("Invalid mousecursor event received! {data:?}");
                     }
+                } else if channel == FLUTTER_RESTORATION_CHANNEL {
+                    match message_codec::from_slice(&data) {
+                        Ok(message) => {
+                            log::debug!("Restoration message: {message:?}");
+                            match this.restoration_handler.handle_message(message) {
+                                Ok(reply) => response = Some(reply),
+                                Err(err) => log::error!("Failed encoding {FLUTTER_RESTORATION_CHANNEL} reply: {err}"),
+                            }
+                        }
+                        Err(err) => log::error!("Failed decoding {FLUTTER_RESTORATION_CHANNEL} message: {err}"),
+                    }
+                } else if channel == FLUTTER_KEYBOARD_CHANNEL {
+                    match message_codec::from_slice(&data) {
+                        Ok(message) => {
+                            log::debug!("Keyboard message: {message:?}");
+                            match this.keyboard.handle_keyboard_message(message) {
+                                Ok(reply) => response = Some(reply),
+                                Err(err) => log::error!("Failed encoding {FLUTTER_KEYBOARD_CHANNEL} reply: {err}"),
+                            }
+                        }
+                        Err(err) => log::error!("Failed decoding {FLUTTER_KEYBOARD_CHANNEL} message: {err}"),
+                    }
                 } else if channel == FLUTTER_PLATFORM_VIEWS_CHANNEL {
                     if let Ok(message) = serde_json::from_slice(&data) {
                         log::debug!("Platform Views Message: {message:?}");
-                        response = this.platform_views_handler.handle_platform_views_message(message);
+                        response = this.platform_views_handler.handle_platform_views_message(this.engine, message);
                     } else {
                         log::error!("Failed decoding {FLUTTER_PLATFORM_VIEWS_CHANNEL} message {:?}", String::from_utf8(data));
                     }
@@ -641,23 +1280,77 @@ impl FlutterApplication {
         });
     }
 
+    /// Returns the first vsync after `now`, extrapolated from the time the
+    /// last frame was presented.
+    fn next_vsync(now: u64, last_present: u64, frame_interval: u64) -> u64 {
+        if last_present == 0 || last_present > now {
+            return now;
+        }
+        let elapsed = now - last_present;
+        last_present + (elapsed / frame_interval + 1) * frame_interval
+    }
+
     extern "C" fn vsync_callback(user_data: *mut c_void, baton: isize) {
         let user_data = unsafe { &*(user_data as *const FlutterApplicationUserData) };
+        Self::schedule_vsync(user_data, baton, 0);
+    }
 
-        user_data
-            .event_loop_proxy
-            .lock()
-            .unwrap()
-            .send_event(Box::new(move |this| {
-                this.device().poll(wgpu::Maintain::Wait);
-                let time = Self::current_time();
-                Self::unwrap_result(unsafe {
-                    FlutterEngineOnVsync(this.engine, baton, time, time + 1000000000 / 60)
-                });
-                false
-            }))
-            .ok()
-            .unwrap();
+    /// Answers the vsync request `baton` at the start of the next frame, but
+    /// not before `not_before`. While too many frames are queued on the GPU,
+    /// the answer is postponed instead of waiting for the GPU, so neither the
+    /// UI thread nor the platform thread block.
+    fn schedule_vsync(user_data: &FlutterApplicationUserData, baton: isize, not_before: u64) {
+        let frame_interval = user_data.frame_interval_nanos.load(Ordering::Relaxed);
+        let now = Self::current_time();
+        let frame_start = if user_data.presentation.is_vsync_aligned() {
+            let last_present = user_data.last_present_nanos.load(Ordering::Relaxed);
+            Self::next_vsync(now, last_present, frame_interval)
+        } else {
+            now
+        }
+        .max(not_before);
+
+        let event_loop_proxy = user_data.event_loop_proxy.lock().unwrap().clone();
+        let callback = user_data.callback(move |this| {
+            // Only collect finished work, never block the platform thread.
+            this.device().poll(wgpu::Maintain::Poll);
+            let user_data = &this.user_data;
+            if user_data.frames_in_flight.load(Ordering::Acquire)
+                >= user_data.presentation.frames_in_flight
+            {
+                // Throttle the engine until the GPU caught up.
+                Self::schedule_vsync(
+                    user_data,
+                    baton,
+                    Self::current_time() + GPU_POLL_INTERVAL_NANOS,
+                );
+                return false;
+            }
+            user_data.perf_hud.vsync(frame_start);
+            Self::unwrap_result(unsafe {
+                FlutterEngineOnVsync(
+                    this.engine,
+                    baton,
+                    frame_start,
+                    frame_start + frame_interval,
+                )
+            });
+            false
+        });
+        Self::spawn_at(&user_data.runtime, frame_start, move || {
+            event_loop_proxy.send_event(callback).ok().unwrap();
+        });
+    }
+
+    /// Runs `f` on `runtime` once [`Self::current_time`] reached `time`.
+    fn spawn_at(runtime: &Runtime, time: u64, f: impl FnOnce() + Send + 'static) {
+        let now = Self::current_time();
+        runtime.spawn(async move {
+            if time > now {
+                tokio::time::sleep(Duration::from_nanos(time - now)).await;
+            }
+            f();
+        });
     }
 
     extern "C" fn on_pre_engine_restart_callback(_user_data: *mut c_void) {
@@ -751,39 +1444,50 @@ impl FlutterApplication {
     ) {
         let user_data = unsafe { &*(user_data as *const FlutterApplicationUserData) };
         let task = SendFlutterTask(task);
+        user_data.perf_hud.platform_task_posted();
 
         if Self::current_time() >= target_time_nanos {
             user_data
                 .event_loop_proxy
                 .lock()
                 .unwrap()
-                .send_event(Box::new(move |application| unsafe {
+                .send_event(user_data.callback(move |application| unsafe {
                     Self::unwrap_result(FlutterEngineRunTask(application.engine, &task.0));
                     drop(task);
+                    application.user_data.perf_hud.platform_task_finished();
                     false
                 }))
                 .ok()
                 .unwrap();
         } else {
             let event_loop_proxy = user_data.event_loop_proxy.lock().unwrap().clone();
-            user_data.runtime.spawn(async move {
-                tokio::time::sleep(Duration::from_nanos(
-                    target_time_nanos - Self::current_time(),
-                ))
-                .await;
-
-                event_loop_proxy
-                    .send_event(Box::new(move |application| unsafe {
-                        Self::unwrap_result(FlutterEngineRunTask(application.engine, &task.0));
-                        drop(task);
-                        false
-                    }))
-                    .ok()
-                    .unwrap();
+            let callback = user_data.callback(move |application| unsafe {
+                Self::unwrap_result(FlutterEngineRunTask(application.engine, &task.0));
+                drop(task);
+                application.user_data.perf_hud.platform_task_finished();
+                false
+            });
+            Self::spawn_at(&user_data.runtime, target_time_nanos, move || {
+                event_loop_proxy.send_event(callback).ok().unwrap();
             });
         }
     }
 
+    /// wgpu has no dedicated device lost notification yet, it shows up as an
+    /// error on the next operation instead. Like wgpu does to tell out of
+    /// memory errors apart, this looks for the device error in the chain of
+    /// causes.
+    fn is_device_lost_error(error: &wgpu::Error) -> bool {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+        while let Some(error) = source {
+            if let Some(DeviceError::Lost) = error.downcast_ref() {
+                return true;
+            }
+            source = error.source();
+        }
+        false
+    }
+
     fn unwrap_result(result: FlutterEngineResult) {
         #[allow(non_upper_case_globals)]
         match result {
@@ -814,3 +1518,127 @@ impl Drop for FlutterApplication {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_metrics_are_physical_pixels_at_the_origin() {
+        let metrics = FlutterApplication::window_metrics(2560, 1440, 2.0);
+        assert_eq!((metrics.width, metrics.height), (2560, 1440));
+        assert_eq!(metrics.pixel_ratio, 2.0);
+        assert_eq!((metrics.left, metrics.top), (0, 0));
+    }
+
+    #[test]
+    fn window_metrics_keep_fractional_pixel_ratios() {
+        let metrics = FlutterApplication::window_metrics(1920, 1080, 1.25);
+        assert_eq!((metrics.width, metrics.height), (1920, 1080));
+        assert_eq!(metrics.pixel_ratio, 1.25);
+    }
+
+    #[test]
+    fn vsync_is_answered_on_the_application_runtime() {
+        let runtime = FlutterApplication::create_runtime().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        // Like a vsync request right after a present.
+        let frame_start = FlutterApplication::current_time() + 16_000_000;
+        FlutterApplication::spawn_at(&runtime, frame_start, move || {
+            sender.send(FlutterApplication::current_time()).unwrap();
+        });
+        // The sender is dropped without a message if the task panicked.
+        let answered = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(answered >= frame_start);
+    }
+
+    #[test]
+    fn throttled_vsync_is_postponed_on_the_application_runtime() {
+        let runtime = FlutterApplication::create_runtime().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        // Every poll of a busy GPU postpones the vsync again.
+        for _ in 0..3 {
+            let not_before = FlutterApplication::current_time() + GPU_POLL_INTERVAL_NANOS;
+            let sender = sender.clone();
+            FlutterApplication::spawn_at(&runtime, not_before, move || {
+                sender.send(FlutterApplication::current_time()).unwrap();
+            });
+            let answered = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(answered >= not_before);
+        }
+    }
+
+    fn mouse_at(position: PhysicalPosition<f64>) -> PointerState {
+        PointerState {
+            virtual_id: 1,
+            position,
+            held_buttons: 0,
+            device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
+            pressure: None,
+            tilt: 0.0,
+            pan_zoom: None,
+        }
+    }
+
+    #[test]
+    fn mouse_positions_stay_in_physical_pixels() {
+        // Where winit reports the cursor in a window with a scale factor of 2.
+        let mouse = mouse_at(PhysicalPosition::new(300.5, 150.25));
+        let event = FlutterApplication::flutter_pointer_event(
+            &mouse,
+            FlutterPointerPhase_kHover,
+            None,
+            2.0 * 53.0,
+            0,
+        );
+        assert_eq!((event.x, event.y), (300.5, 150.25));
+        assert_eq!((event.scroll_delta_x, event.scroll_delta_y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn line_scrolls_are_scaled_to_physical_pixels() {
+        let mouse = mouse_at(PhysicalPosition::new(40.0, 60.0));
+        let event = FlutterApplication::flutter_pointer_event(
+            &mouse,
+            FlutterPointerPhase_kHover,
+            Some(MouseScrollDelta::LineDelta(0.0, -2.0)),
+            1.5 * 20.0,
+            0,
+        );
+        assert_eq!((event.x, event.y), (40.0, 60.0));
+        assert_eq!((event.scroll_delta_x, event.scroll_delta_y), (0.0, -60.0));
+        // Pixel deltas already are physical.
+        let event = FlutterApplication::flutter_pointer_event(
+            &mouse,
+            FlutterPointerPhase_kHover,
+            Some(MouseScrollDelta::PixelDelta(PhysicalPosition::new(
+                3.0, 9.0,
+            ))),
+            1.5 * 20.0,
+            0,
+        );
+        assert_eq!((event.scroll_delta_x, event.scroll_delta_y), (3.0, 9.0));
+    }
+
+    #[test]
+    fn touch_positions_stay_in_physical_pixels() {
+        let mut touches = Touches::default();
+        let mut next_pointer_id = 0;
+        let touch = Touch {
+            // Not an actual device, the touches only need a consistent id.
+            device_id: unsafe { DeviceId::dummy() },
+            phase: TouchPhase::Started,
+            // Where winit reports the finger in a window with a scale factor
+            // of 1.5.
+            location: PhysicalPosition::new(301.5, 99.0),
+            force: None,
+            id: 0,
+        };
+        let events = touches.update(&touch, &mut next_pointer_id);
+        assert_eq!(events.len(), 2);
+        for (pointer, phase) in events {
+            let event = FlutterApplication::flutter_pointer_event(&pointer, phase, None, 1.5, 0);
+            assert_eq!((event.x, event.y), (301.5, 99.0));
+        }
+    }
+}
@@ -1,11 +1,21 @@
-use std::{cell::Cell, ffi::c_void, mem::size_of, ptr::null_mut};
+use std::{
+    cell::Cell,
+    ffi::c_void,
+    mem::size_of,
+    num::NonZeroU32,
+    ptr::null_mut,
+    sync::{atomic::Ordering, Arc},
+};
 
-use ash::vk::Handle;
+use ash::vk::{self, Handle};
 use wgpu::{
-    CommandEncoderDescriptor, Extent3d, ImageCopyTextureBase, Origin3d, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, CommandEncoderDescriptor, Device,
+    Extent3d, ImageCopyBuffer, ImageCopyTextureBase, ImageDataLayout, ImageSubresourceRange,
+    Origin3d, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 use wgpu_hal::api::Vulkan;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 
 use crate::{
     flutter_application::FlutterApplication,
@@ -14,12 +24,87 @@ use crate::{
         FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan, FlutterBackingStore__bindgen_ty_1,
         FlutterCompositor, FlutterLayer,
         FlutterLayerContentType_kFlutterLayerContentTypeBackingStore,
-        FlutterLayerContentType_kFlutterLayerContentTypePlatformView, FlutterVulkanBackingStore,
-        FlutterVulkanImage,
+        FlutterLayerContentType_kFlutterLayerContentTypePlatformView,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRect,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRoundedRect,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeTransformation,
+        FlutterRect, FlutterTransformation, FlutterVulkanBackingStore, FlutterVulkanImage,
     },
 };
 
-use super::FlutterApplicationUserData;
+use super::{
+    damage::{self, DamageLayer, DamageRect, LayerContent},
+    perf_hud::{PerfHud, PerfHudFrame},
+    platform_views::{PlatformViewClip, PlatformViewLayer},
+    vulkan_sync, FlutterApplicationUserData, RenderTarget,
+};
+
+const IDENTITY_TRANSFORMATION: FlutterTransformation = FlutterTransformation {
+    scaleX: 1.0,
+    skewX: 0.0,
+    transX: 0.0,
+    skewY: 0.0,
+    scaleY: 1.0,
+    transY: 0.0,
+    pers0: 0.0,
+    pers1: 0.0,
+    pers2: 1.0,
+};
+
+fn concat_transformations(
+    a: &FlutterTransformation,
+    b: &FlutterTransformation,
+) -> FlutterTransformation {
+    FlutterTransformation {
+        scaleX: a.scaleX * b.scaleX + a.skewX * b.skewY + a.transX * b.pers0,
+        skewX: a.scaleX * b.skewX + a.skewX * b.scaleY + a.transX * b.pers1,
+        transX: a.scaleX * b.transX + a.skewX * b.transY + a.transX * b.pers2,
+        skewY: a.skewY * b.scaleX + a.scaleY * b.skewY + a.transY * b.pers0,
+        scaleY: a.skewY * b.skewX + a.scaleY * b.scaleY + a.transY * b.pers1,
+        transY: a.skewY * b.transX + a.scaleY * b.transY + a.transY * b.pers2,
+        pers0: a.pers0 * b.scaleX + a.pers1 * b.skewY + a.pers2 * b.pers0,
+        pers1: a.pers0 * b.skewX + a.pers1 * b.scaleY + a.pers2 * b.pers1,
+        pers2: a.pers0 * b.transX + a.pers1 * b.transY + a.pers2 * b.pers2,
+    }
+}
+
+fn transform_point(t: &FlutterTransformation, x: f64, y: f64) -> (f64, f64) {
+    let w = t.pers0 * x + t.pers1 * y + t.pers2;
+    (
+        (t.scaleX * x + t.skewX * y + t.transX) / w,
+        (t.skewY * x + t.scaleY * y + t.transY) / w,
+    )
+}
+
+/// Returns the axis-aligned bounds of the transformed rectangle as
+/// `(left, top, right, bottom)`.
+fn transform_rect(t: &FlutterTransformation, rect: &FlutterRect) -> (f64, f64, f64, f64) {
+    let corners = [
+        transform_point(t, rect.left, rect.top),
+        transform_point(t, rect.right, rect.top),
+        transform_point(t, rect.right, rect.bottom),
+        transform_point(t, rect.left, rect.bottom),
+    ];
+    corners.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(left, top, right, bottom), &(x, y)| {
+            (left.min(x), top.min(y), right.max(x), bottom.max(y))
+        },
+    )
+}
+
+/// Owned by the engine through the `user_data` of the Vulkan backing store.
+struct BackingStore {
+    texture: Texture,
+    image: FlutterVulkanImage,
+    size_bytes: usize,
+    perf_hud: Arc<PerfHud>,
+}
 
 pub struct Compositor {
     platform_view_count: Cell<i64>,
@@ -51,6 +136,9 @@ impl Compositor {
         let application_user_data = unsafe {
             &*(user_data as *const FlutterApplicationUserData) as &FlutterApplicationUserData
         };
+        if application_user_data.device_lost.load(Ordering::Acquire) {
+            return false;
+        }
 
         let texture = application_user_data
             .device
@@ -65,10 +153,17 @@ impl Compositor {
                 sample_count: 1,
                 dimension: TextureDimension::D2,
                 format: TextureFormat::Bgra8Unorm,
+                // Matches the usage flags Skia assumes for backing stores.
                 usage: TextureUsages::COPY_SRC
+                    | TextureUsages::COPY_DST
                     | TextureUsages::RENDER_ATTACHMENT
                     | TextureUsages::TEXTURE_BINDING,
             });
+        vulkan_sync::prepare_backing_store(
+            &application_user_data.device,
+            &application_user_data.queue,
+            &texture,
+        );
 
         let mut image = None;
         unsafe {
@@ -82,7 +177,17 @@ impl Compositor {
             });
         }
         let image = image.unwrap();
-        let user_data = Box::new((texture, image));
+        let size = unsafe { *config }.size;
+        let size_bytes = size.width as usize * size.height as usize * 4;
+        application_user_data
+            .perf_hud
+            .backing_store_created(size_bytes);
+        let user_data = Box::new(BackingStore {
+            texture,
+            image,
+            size_bytes,
+            perf_hud: application_user_data.perf_hud.clone(),
+        });
         let mut backing_store = unsafe { &mut *backing_store_out as &mut FlutterBackingStore };
         backing_store.user_data = null_mut();
         backing_store.type_ = FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan;
@@ -90,7 +195,7 @@ impl Compositor {
         backing_store.__bindgen_anon_1 = FlutterBackingStore__bindgen_ty_1 {
             vulkan: FlutterVulkanBackingStore {
                 struct_size: size_of::<FlutterVulkanBackingStore>() as _,
-                image: &user_data.1,
+                image: &user_data.image,
                 user_data: Box::into_raw(user_data) as _,
                 destruction_callback: Some(Self::destroy_texture),
             },
@@ -98,9 +203,11 @@ impl Compositor {
         true
     }
     extern "C" fn destroy_texture(user_data: *mut c_void) {
-        let (texture, _image) =
-            *unsafe { Box::from_raw(user_data as *mut (Texture, FlutterVulkanImage)) };
-        texture.destroy();
+        let backing_store = unsafe { Box::from_raw(user_data as *mut BackingStore) };
+        backing_store
+            .perf_hud
+            .backing_store_destroyed(backing_store.size_bytes);
+        backing_store.texture.destroy();
     }
     extern "C" fn present_layers_callback(
         layers: *mut *const FlutterLayer,
@@ -108,90 +215,385 @@ impl Compositor {
         user_data: *mut c_void,
     ) -> bool {
         let application_user_data = unsafe { &*(user_data as *const FlutterApplicationUserData) };
+        let present_start = FlutterApplication::current_time();
+        if application_user_data.device_lost.load(Ordering::Acquire) {
+            return false;
+        }
 
-        let frame = application_user_data
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
+        let layers: Vec<&FlutterLayer> =
+            unsafe { std::slice::from_raw_parts(layers, layers_count as _) }
+                .iter()
+                .map(|&layer| unsafe { &*layer } as &FlutterLayer)
+                .collect();
+
+        let mut platform_view_layers = vec![];
+        let mut damage_layers = vec![];
+        let mut frame_size = (0, 0);
+        for (idx, &layer) in layers.iter().enumerate() {
+            let offset = layer.offset;
+            let size = layer.size;
+            frame_size = (
+                frame_size.0.max((offset.x + size.width) as u32),
+                frame_size.1.max((offset.y + size.height) as u32),
+            );
+            log::debug!("Layer {idx} type {}", layer.type_);
+            let rect = DamageRect::new(
+                offset.x as _,
+                offset.y as _,
+                size.width as _,
+                size.height as _,
+            );
+            match layer.type_ {
+                x if x == FlutterLayerContentType_kFlutterLayerContentTypeBackingStore => {
+                    let backing_store = unsafe { &*layer.__bindgen_anon_1.backing_store };
+                    assert_eq!(
+                        backing_store.type_,
+                        FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan
+                    );
+                    damage_layers.push(DamageLayer {
+                        content: LayerContent::BackingStore(
+                            unsafe { &backing_store.__bindgen_anon_1.vulkan }.user_data as _,
+                        ),
+                        rect,
+                        did_update: backing_store.did_update,
+                    });
+                }
+                x if x == FlutterLayerContentType_kFlutterLayerContentTypePlatformView => {
+                    let platform_view = unsafe { &*layer.__bindgen_anon_1.platform_view };
+                    log::error!(
+                        "Rendering platform view {}: not implemented yet!",
+                        platform_view.identifier
+                    );
+                    damage_layers.push(DamageLayer {
+                        content: LayerContent::PlatformView(platform_view.identifier),
+                        rect,
+                        did_update: false,
+                    });
+                    platform_view_layers.push(Self::platform_view_layer(layer));
+                }
+                _ => panic!("Invalid layer type"),
+            }
+        }
+        *application_user_data.platform_view_layers.lock().unwrap() = platform_view_layers;
+
+        let render_target = &application_user_data.render_target;
+        let mut damage_tracker = application_user_data.damage.lock().unwrap();
+        let full_damage = match render_target {
+            RenderTarget::Surface(_) => damage_tracker.composition().is_none(),
+            RenderTarget::Texture(target) => {
+                damage_tracker.target_changed(Arc::as_ptr(&target.texture()) as _)
+            }
+        };
+        let damage = damage_tracker.update(
+            damage_layers,
+            frame_size,
+            full_damage,
+            application_user_data.perf_hud.damage(),
+        );
+        if damage.is_empty() {
+            log::trace!("Nothing changed, skipping frame");
+            return true;
+        }
+        if matches!(render_target, RenderTarget::Surface(_))
+            && damage_tracker.composition().is_none()
+        {
+            damage_tracker.set_composition(Self::create_composition_texture(
+                &application_user_data.device,
+                frame_size,
+            ));
+        }
+
+        let frame = match render_target.acquire_frame(&application_user_data.device) {
+            Some(frame) => frame,
+            None => {
+                // Nothing of this frame made it to the screen.
+                damage_tracker.invalidate();
+                return false;
+            }
+        };
+        let target_size = frame.size();
+        // Without a composition texture, the layers are copied straight into
+        // the target, which the host might have replaced with a smaller one.
+        let damage = match damage_tracker.composition() {
+            Some(_) => damage,
+            None => damage::clip(damage, target_size),
+        };
+        let composition = damage_tracker.composition().unwrap_or(frame.texture());
         let mut encoder = application_user_data
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
-        {
-            // encoder.clear_texture(&frame.texture, &ImageSubresourceRange::default());
-            // encoder.begin_render_pass(&RenderPassDescriptor {
-            //     label: None,
-            //     color_attachments: &[Some(RenderPassColorAttachment {
-            //         view: &view,
-            //         resolve_target: None,
-            //         ops: Operations {
-            //             load: LoadOp::Clear(Color {
-            //                 r: 0.0,
-            //                 g: 1.0,
-            //                 b: 0.0,
-            //                 a: 1.0,
-            //             }),
-            //             store: true,
-            //         },
-            //     })],
-            //     depth_stencil_attachment: None,
-            // });
-
-            for (idx, &layer) in unsafe { std::slice::from_raw_parts(layers, layers_count as _) }
-                .iter()
-                .map(|&layer| unsafe { &*layer } as &FlutterLayer)
-                .enumerate()
-            {
-                let offset = layer.offset;
-                let size = layer.size;
-                log::debug!("Layer {idx} type {}", layer.type_);
-                match layer.type_ {
-                    x if x == FlutterLayerContentType_kFlutterLayerContentTypeBackingStore => {
-                        let backing_store = unsafe { &*layer.__bindgen_anon_1.backing_store };
-                        assert_eq!(
-                            backing_store.type_,
-                            FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan
-                        );
-                        let backing_store = unsafe { &backing_store.__bindgen_anon_1.vulkan };
-                        let texture = unsafe { &*(backing_store.user_data as *mut Texture) };
-
-                        encoder.copy_texture_to_texture(
-                            ImageCopyTextureBase {
-                                texture,
-                                mip_level: 0,
-                                origin: Origin3d::ZERO,
-                                aspect: TextureAspect::All,
-                            },
-                            ImageCopyTextureBase {
-                                texture: &frame.texture,
-                                mip_level: 0,
-                                origin: Origin3d {
-                                    x: offset.x as _,
-                                    y: offset.y as _,
-                                    z: 0,
-                                },
-                                aspect: TextureAspect::All,
-                            },
-                            Extent3d {
-                                width: size.width as _,
-                                height: size.height as _,
-                                depth_or_array_layers: 1,
-                            },
-                        );
-                    }
-                    x if x == FlutterLayerContentType_kFlutterLayerContentTypePlatformView => {
-                        log::error!(
-                            "Rendering platform view {}: not implemented yet!",
-                            unsafe { &*layer.__bindgen_anon_1.platform_view }.identifier
-                        );
-                    }
-                    _ => panic!("Invalid layer type"),
+        let mut images = vec![];
+        let mut zero_buffer = application_user_data.zero_buffer.lock().unwrap();
+        let zeroes = Self::zero_buffer(
+            &application_user_data.device,
+            &mut zero_buffer,
+            damage.iter().map(Self::clear_size).max().unwrap_or(0),
+        );
+        for dirty in &damage {
+            // Areas without any layer stay transparent instead of showing
+            // whatever was there before.
+            Self::clear_rect(&mut encoder, zeroes, composition, dirty);
+            for (layer, damage_layer) in layers.iter().zip(damage_tracker.layers()) {
+                if layer.type_ != FlutterLayerContentType_kFlutterLayerContentTypeBackingStore {
+                    continue;
                 }
+                let rect = match damage_layer.rect.intersection(dirty) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                let backing_store = unsafe { &*layer.__bindgen_anon_1.backing_store };
+                let backing_store = unsafe { &backing_store.__bindgen_anon_1.vulkan };
+                let backing_store = unsafe { &*(backing_store.user_data as *const BackingStore) };
+                let texture = &backing_store.texture;
+                let image = vk::Image::from_raw(backing_store.image.image);
+                if !images.contains(&image) {
+                    images.push(image);
+                }
+
+                encoder.copy_texture_to_texture(
+                    ImageCopyTextureBase {
+                        texture,
+                        mip_level: 0,
+                        origin: Origin3d {
+                            x: rect.x - damage_layer.rect.x,
+                            y: rect.y - damage_layer.rect.y,
+                            z: 0,
+                        },
+                        aspect: TextureAspect::All,
+                    },
+                    ImageCopyTextureBase {
+                        texture: composition,
+                        mip_level: 0,
+                        origin: Origin3d {
+                            x: rect.x,
+                            y: rect.y,
+                            z: 0,
+                        },
+                        aspect: TextureAspect::All,
+                    },
+                    Extent3d {
+                        width: rect.width,
+                        height: rect.height,
+                        depth_or_array_layers: 1,
+                    },
+                );
             }
         }
+        if let Some(composition) = damage_tracker.composition() {
+            // The swapchain images don't keep their contents, so the whole
+            // composition has to be copied every time. While resizing, the
+            // surface might be larger or smaller than the layers.
+            encoder.clear_texture(frame.texture(), &ImageSubresourceRange::default());
+            encoder.copy_texture_to_texture(
+                ImageCopyTextureBase {
+                    texture: composition,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyTextureBase {
+                    texture: frame.texture(),
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                Extent3d {
+                    width: frame_size.0.min(target_size.0),
+                    height: frame_size.1.min(target_size.1),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        drop(damage_tracker);
+
+        application_user_data.perf_hud.draw(
+            &application_user_data.device,
+            &application_user_data.queue,
+            &mut encoder,
+            PerfHudFrame {
+                target: frame.texture(),
+                target_size,
+                layers: layers_count as _,
+                present_start,
+            },
+        );
+        let mut vulkan_sync = application_user_data.vulkan_sync.lock().unwrap();
+        if let Err(err) = vulkan_sync.acquire(&application_user_data.device, &images) {
+            log::error!("Failed to hand backing stores over to wgpu: {err}");
+            application_user_data.damage.lock().unwrap().invalidate();
+            return false;
+        }
         application_user_data.queue.submit(Some(encoder.finish()));
-        frame.present();
+        if let Err(err) = vulkan_sync.release(&application_user_data.device, &images) {
+            log::error!("Failed to hand backing stores back to the engine: {err}");
+        }
+        drop(vulkan_sync);
+        let frames_in_flight = application_user_data.frames_in_flight.clone();
+        frames_in_flight.fetch_add(1, Ordering::AcqRel);
+        application_user_data.queue.on_submitted_work_done(move || {
+            frames_in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+        render_target.present(frame);
+        application_user_data
+            .last_present_nanos
+            .store(FlutterApplication::current_time(), Ordering::Relaxed);
+        application_user_data.perf_hud.presented(present_start);
         true
     }
+
+    fn clear_bytes_per_row(rect: &DamageRect) -> u32 {
+        (rect.width * 4 + COPY_BYTES_PER_ROW_ALIGNMENT - 1) / COPY_BYTES_PER_ROW_ALIGNMENT
+            * COPY_BYTES_PER_ROW_ALIGNMENT
+    }
+
+    /// The size of the buffer [`Self::clear_rect`] needs for `rect`.
+    fn clear_size(rect: &DamageRect) -> u64 {
+        Self::clear_bytes_per_row(rect) as u64 * rect.height as u64
+    }
+
+    /// Returns the cached zero buffer, replacing it first if it's smaller
+    /// than `size`.
+    fn zero_buffer<'a>(
+        device: &Device,
+        cache: &'a mut Option<(Buffer, u64)>,
+        size: u64,
+    ) -> &'a Buffer {
+        if !matches!(cache, Some((_, cached)) if *cached >= size) {
+            let buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("Flutter Clear"),
+                size: size.max(COPY_BYTES_PER_ROW_ALIGNMENT as _),
+                usage: BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            *cache = Some((buffer, size));
+        }
+        &cache.as_ref().unwrap().0
+    }
+
+    /// Zeroes a rectangle of `texture`. `clear_texture` only works on whole
+    /// textures, so this copies from `zeroes`, which has to be at least
+    /// [`Self::clear_size`] bytes large.
+    fn clear_rect(
+        encoder: &mut CommandEncoder,
+        zeroes: &Buffer,
+        texture: &Texture,
+        rect: &DamageRect,
+    ) {
+        let bytes_per_row = Self::clear_bytes_per_row(rect);
+        encoder.copy_buffer_to_texture(
+            ImageCopyBuffer {
+                buffer: zeroes,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            ImageCopyTextureBase {
+                texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: rect.x,
+                    y: rect.y,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn create_composition_texture(device: &Device, size: (u32, u32)) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some("Flutter Composition"),
+            size: Extent3d {
+                width: size.0.max(1),
+                height: size.1.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8Unorm,
+            usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+        })
+    }
+
+    /// Extracts the geometry needed for hit testing from a platform view
+    /// layer, applying all of its clip mutations.
+    fn platform_view_layer(layer: &FlutterLayer) -> PlatformViewLayer {
+        let platform_view = unsafe { &*layer.__bindgen_anon_1.platform_view };
+        let mutations = if platform_view.mutations_count > 0 {
+            unsafe {
+                std::slice::from_raw_parts(
+                    platform_view.mutations,
+                    platform_view.mutations_count as _,
+                )
+            }
+        } else {
+            &[]
+        };
+
+        let mut transformation = IDENTITY_TRANSFORMATION;
+        let mut clips = vec![];
+        for &mutation in mutations {
+            let mutation = unsafe { &*mutation };
+            match mutation.type_ {
+                x if x == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeTransformation => {
+                    transformation = concat_transformations(&transformation, unsafe {
+                        &mutation.__bindgen_anon_1.transformation
+                    });
+                }
+                x if x == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRect => {
+                    let (left, top, right, bottom) = transform_rect(&transformation, unsafe {
+                        &mutation.__bindgen_anon_1.clip_rect
+                    });
+                    clips.push(PlatformViewClip::Rect {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                    });
+                }
+                x if x == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRoundedRect => {
+                    let rounded_rect = unsafe { &mutation.__bindgen_anon_1.clip_rounded_rect };
+                    let (left, top, right, bottom) =
+                        transform_rect(&transformation, &rounded_rect.rect);
+                    // Corner radii are scaled along, rotations are ignored.
+                    let scale_x = (right - left) / (rounded_rect.rect.right - rounded_rect.rect.left);
+                    let scale_y = (bottom - top) / (rounded_rect.rect.bottom - rounded_rect.rect.top);
+                    let radii = [
+                        rounded_rect.upper_left_corner_radius,
+                        rounded_rect.upper_right_corner_radius,
+                        rounded_rect.lower_right_corner_radius,
+                        rounded_rect.lower_left_corner_radius,
+                    ]
+                    .map(|radius| PhysicalSize::new(radius.width * scale_x, radius.height * scale_y));
+                    clips.push(PlatformViewClip::RoundedRect {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                        radii,
+                    });
+                }
+                _ => {
+                    // Opacity doesn't affect hit testing.
+                }
+            }
+        }
+
+        PlatformViewLayer {
+            id: platform_view.identifier as _,
+            offset: PhysicalPosition::new(layer.offset.x, layer.offset.y),
+            size: PhysicalSize::new(layer.size.width, layer.size.height),
+            clips,
+        }
+    }
     extern "C" fn backing_store_collect_callback(
         _renderer: *const FlutterBackingStore,
         _user_data: *mut c_void,
@@ -0,0 +1,395 @@
+//! Tracks which parts of the frame changed since the last present.
+//!
+//! The engine only tells us whether a backing store was rendered to since it
+//! was last presented (`did_update`), not which pixels changed, so damage is
+//! tracked with layer granularity. Layers that were added, removed, moved or
+//! resized damage both their old and their new rectangle.
+//!
+//! wgpu has no API to pass damage rectangles to the presentation engine
+//! (`VK_KHR_incremental_present`), so a window surface is always presented in
+//! full. Recomposition is still restricted to the damaged rectangles, and
+//! frames without any damage aren't presented at all.
+
+use wgpu::Texture;
+
+/// Above this, the rectangles are merged into their bounding box, since
+/// issuing many small copies is slower than one larger copy.
+const MAX_DAMAGE_RECTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct DamageRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DamageRect {
+    pub(super) fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub(super) fn intersection(&self, other: &Self) -> Option<Self> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > x && bottom > y).then(|| Self::new(x, y, right - x, bottom - y))
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+}
+
+/// Restricts `damage` to a target of `size`, dropping rectangles outside of
+/// it.
+pub(super) fn clip(damage: Vec<DamageRect>, size: (u32, u32)) -> Vec<DamageRect> {
+    let bounds = DamageRect::new(0, 0, size.0, size.1);
+    damage
+        .iter()
+        .filter_map(|rect| rect.intersection(&bounds))
+        .collect()
+}
+
+/// What a layer shows, used to detect changes in the layer tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LayerContent {
+    /// Identified by the address of its `user_data`.
+    BackingStore(usize),
+    PlatformView(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct DamageLayer {
+    pub content: LayerContent,
+    pub rect: DamageRect,
+    /// Set by the engine if the backing store was rendered to.
+    pub did_update: bool,
+}
+
+/// The composition retained between frames.
+pub(super) struct DamageTracker {
+    layers: Vec<DamageLayer>,
+    size: (u32, u32),
+    /// Identifies the host texture of a texture target, to notice when it
+    /// gets replaced.
+    target: usize,
+    /// A copy of the last composited frame, only used for window surfaces,
+    /// since the swapchain doesn't preserve the contents of its images.
+    composition: Option<Texture>,
+}
+
+impl Default for DamageTracker {
+    fn default() -> Self {
+        Self {
+            layers: vec![],
+            size: (0, 0),
+            target: 0,
+            composition: None,
+        }
+    }
+}
+
+impl DamageTracker {
+    /// Returns the rectangles that need to be recomposited and remembers the
+    /// layers for the next frame. `full_damage` forces the whole frame to be
+    /// redrawn, for example because the render target was replaced.
+    pub(super) fn update(
+        &mut self,
+        layers: Vec<DamageLayer>,
+        size: (u32, u32),
+        full_damage: bool,
+        extra_damage: Option<DamageRect>,
+    ) -> Vec<DamageRect> {
+        let mut damage = vec![];
+        if full_damage || size != self.size {
+            self.composition = None;
+            damage.push(DamageRect::new(0, 0, size.0, size.1));
+        } else {
+            for idx in 0..self.layers.len().max(layers.len()) {
+                match (self.layers.get(idx), layers.get(idx)) {
+                    (Some(old), Some(new))
+                        if old.content == new.content && old.rect == new.rect =>
+                    {
+                        if new.did_update {
+                            damage.push(new.rect);
+                        }
+                    }
+                    (old, new) => {
+                        damage.extend(old.map(|layer| layer.rect));
+                        damage.extend(new.map(|layer| layer.rect));
+                    }
+                }
+            }
+            damage.extend(extra_damage);
+        }
+        self.layers = layers;
+        self.size = size;
+        Self::simplify(damage)
+    }
+
+    fn simplify(damage: Vec<DamageRect>) -> Vec<DamageRect> {
+        let mut merged: Vec<DamageRect> = vec![];
+        for mut rect in damage.into_iter().filter(|rect| !rect.is_empty()) {
+            // Merging can make a rectangle overlap ones that were checked
+            // before, so repeat until it's disjoint from all of them.
+            while let Some(idx) = merged
+                .iter()
+                .position(|other| rect.intersection(other).is_some())
+            {
+                rect = rect.union(&merged.swap_remove(idx));
+            }
+            merged.push(rect);
+        }
+        if merged.len() > MAX_DAMAGE_RECTS {
+            let bounds = merged.iter().skip(1).fold(merged[0], |a, b| a.union(b));
+            vec![bounds]
+        } else {
+            merged
+        }
+    }
+
+    /// Forces a full repaint with the next frame.
+    pub(super) fn invalidate(&mut self) {
+        self.layers.clear();
+        self.size = (0, 0);
+        self.composition = None;
+    }
+
+    /// The layers passed to the last [`Self::update`].
+    pub(super) fn layers(&self) -> &[DamageLayer] {
+        &self.layers
+    }
+
+    pub(super) fn target_changed(&mut self, target: usize) -> bool {
+        std::mem::replace(&mut self.target, target) != target
+    }
+
+    pub(super) fn composition(&self) -> Option<&Texture> {
+        self.composition.as_ref()
+    }
+
+    pub(super) fn set_composition(&mut self, texture: Texture) {
+        self.composition = Some(texture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut rects: Vec<DamageRect>) -> Vec<DamageRect> {
+        rects.sort_by_key(|rect| (rect.y, rect.x, rect.width, rect.height));
+        rects
+    }
+
+    fn layer(id: usize, rect: DamageRect, did_update: bool) -> DamageLayer {
+        DamageLayer {
+            content: LayerContent::BackingStore(id),
+            rect,
+            did_update,
+        }
+    }
+
+    /// A tracker that already composited `layers` at 100x100.
+    fn tracker(layers: Vec<DamageLayer>) -> DamageTracker {
+        let mut tracker = DamageTracker::default();
+        tracker.update(layers, (100, 100), false, None);
+        tracker
+    }
+
+    #[test]
+    fn overlapping_rects_are_merged() {
+        let damage = DamageTracker::simplify(vec![
+            DamageRect::new(0, 0, 10, 10),
+            DamageRect::new(5, 5, 10, 10),
+        ]);
+        assert_eq!(damage, [DamageRect::new(0, 0, 15, 15)]);
+    }
+
+    #[test]
+    fn merging_repeats_until_disjoint() {
+        // Merged with the first one, the last one overlaps the second one.
+        let damage = DamageTracker::simplify(vec![
+            DamageRect::new(0, 0, 10, 30),
+            DamageRect::new(20, 20, 10, 10),
+            DamageRect::new(5, 0, 20, 5),
+        ]);
+        assert_eq!(damage, [DamageRect::new(0, 0, 30, 30)]);
+    }
+
+    #[test]
+    fn adjacent_rects_stay_separate() {
+        let damage = DamageTracker::simplify(vec![
+            DamageRect::new(0, 0, 10, 10),
+            DamageRect::new(10, 0, 10, 10),
+            DamageRect::new(0, 10, 10, 10),
+        ]);
+        assert_eq!(
+            sorted(damage),
+            [
+                DamageRect::new(0, 0, 10, 10),
+                DamageRect::new(10, 0, 10, 10),
+                DamageRect::new(0, 10, 10, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_rects_are_dropped() {
+        let damage = DamageTracker::simplify(vec![
+            DamageRect::new(5, 5, 0, 10),
+            DamageRect::new(5, 5, 10, 0),
+        ]);
+        assert!(damage.is_empty());
+        // Even where they would connect two other rects.
+        let damage = DamageTracker::simplify(vec![
+            DamageRect::new(0, 0, 10, 10),
+            DamageRect::new(5, 0, 0, 30),
+            DamageRect::new(0, 20, 10, 10),
+        ]);
+        assert_eq!(damage.len(), 2);
+    }
+
+    #[test]
+    fn too_many_rects_become_their_bounds() {
+        let damage = DamageTracker::simplify(
+            (0..=MAX_DAMAGE_RECTS as u32)
+                .map(|idx| DamageRect::new(idx * 10, 5, 5, 5))
+                .collect(),
+        );
+        assert_eq!(
+            damage,
+            [DamageRect::new(0, 5, MAX_DAMAGE_RECTS as u32 * 10 + 5, 5)]
+        );
+    }
+
+    #[test]
+    fn the_first_frame_is_fully_damaged() {
+        let mut tracker = DamageTracker::default();
+        let layers = vec![layer(1, DamageRect::new(10, 10, 10, 10), true)];
+        assert_eq!(
+            tracker.update(layers, (100, 50), false, None),
+            [DamageRect::new(0, 0, 100, 50)]
+        );
+    }
+
+    #[test]
+    fn only_updated_layers_are_damaged() {
+        let a = DamageRect::new(0, 0, 10, 10);
+        let b = DamageRect::new(50, 50, 10, 10);
+        let mut tracker = tracker(vec![layer(1, a, true), layer(2, b, true)]);
+        assert!(tracker
+            .update(
+                vec![layer(1, a, false), layer(2, b, false)],
+                (100, 100),
+                false,
+                None
+            )
+            .is_empty());
+        assert_eq!(
+            tracker.update(
+                vec![layer(1, a, false), layer(2, b, true)],
+                (100, 100),
+                false,
+                None
+            ),
+            [b]
+        );
+    }
+
+    #[test]
+    fn moved_layers_damage_both_rects() {
+        let old = DamageRect::new(0, 0, 10, 10);
+        let new = DamageRect::new(40, 0, 10, 10);
+        let mut tracker = tracker(vec![layer(1, old, false)]);
+        assert_eq!(
+            sorted(tracker.update(vec![layer(1, new, false)], (100, 100), false, None)),
+            [old, new]
+        );
+    }
+
+    #[test]
+    fn removed_and_replaced_layers_are_damaged() {
+        let a = DamageRect::new(0, 0, 10, 10);
+        let b = DamageRect::new(50, 50, 10, 10);
+        let mut tracker = tracker(vec![layer(1, a, false), layer(2, b, false)]);
+        assert_eq!(
+            tracker.update(vec![layer(1, a, false)], (100, 100), false, None),
+            [b]
+        );
+        assert_eq!(
+            tracker.update(vec![layer(3, a, false)], (100, 100), false, None),
+            [a]
+        );
+    }
+
+    #[test]
+    fn resizing_and_invalidating_damage_everything() {
+        let layers = vec![layer(1, DamageRect::new(0, 0, 10, 10), false)];
+        let mut tracker = tracker(layers.clone());
+        assert_eq!(
+            tracker.update(layers.clone(), (80, 60), false, None),
+            [DamageRect::new(0, 0, 80, 60)]
+        );
+        assert_eq!(
+            tracker.update(layers.clone(), (80, 60), true, None),
+            [DamageRect::new(0, 0, 80, 60)]
+        );
+        tracker.invalidate();
+        assert!(tracker.layers().is_empty());
+        assert_eq!(
+            tracker.update(layers, (80, 60), false, None),
+            [DamageRect::new(0, 0, 80, 60)]
+        );
+    }
+
+    #[test]
+    fn shrunk_targets_clip_the_damage() {
+        let damage = vec![
+            DamageRect::new(0, 0, 100, 100),
+            DamageRect::new(40, 10, 20, 20),
+            DamageRect::new(60, 60, 10, 10),
+        ];
+        assert_eq!(
+            clip(damage, (50, 50)),
+            [
+                DamageRect::new(0, 0, 50, 50),
+                DamageRect::new(40, 10, 10, 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_damage_is_added() {
+        let layers = vec![layer(1, DamageRect::new(0, 0, 10, 10), false)];
+        let mut tracker = tracker(layers.clone());
+        let hud = DamageRect::new(0, 0, 20, 20);
+        assert_eq!(tracker.update(layers, (100, 100), false, Some(hud)), [hud]);
+    }
+}
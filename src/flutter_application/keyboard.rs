@@ -1,14 +1,20 @@
 use std::{
+    collections::{HashMap, VecDeque},
     ffi::CString,
     mem::size_of,
+    ops::Range,
+    os::raw::c_void,
     ptr::{null, null_mut},
     sync::{Arc, Mutex},
 };
 
 use arboard::Clipboard;
+use serde::Deserialize;
 use winit::{
-    event::{ElementState, KeyEvent},
-    keyboard::{Key, ModifiersState},
+    dpi::PhysicalPosition,
+    event::{ElementState, Ime, KeyEvent},
+    keyboard::{Key, KeyCode, ModifiersState},
+    window::Window,
 };
 
 use crate::{
@@ -16,19 +22,166 @@ use crate::{
     flutter_application::{text_input::TextInputClient, FlutterApplication},
     flutter_bindings::{
         FlutterEngine, FlutterEngineSendKeyEvent, FlutterEngineSendPlatformMessage,
-        FlutterKeyEvent, FlutterKeyEventType_kFlutterKeyEventTypeDown,
+        FlutterKeyEvent, FlutterKeyEventType, FlutterKeyEventType_kFlutterKeyEventTypeDown,
         FlutterKeyEventType_kFlutterKeyEventTypeRepeat, FlutterKeyEventType_kFlutterKeyEventTypeUp,
-        FlutterPlatformMessage,
+        FlutterPlatformMessage, FlutterPlatformMessageCreateResponseHandle,
+        FlutterPlatformMessageReleaseResponseHandle,
     },
-    keyboard_logical_key_map::translate_logical_key,
+    keyboard_logical_key_map::translate_event_logical_key,
     keyboard_physical_key_map::translate_physical_key,
 };
 
 use super::{
-    text_input::{TextEditingValue, TextInput, TextInputAction},
-    FLUTTER_TEXTINPUT_CHANNEL,
+    keyboard_event::{FlutterKeyboardEvent, FlutterKeyboardEventResponse},
+    message_codec,
+    text_input::{TextEditingValue, TextInput, TextInputAction, TextRect},
+    FlutterApplicationUserData, FLUTTER_KEYEVENT_CHANNEL, FLUTTER_TEXTINPUT_CHANNEL,
 };
 
+/// Messages on the `flutter/keyboard` channel, encoded with the standard
+/// method codec.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) enum KeyboardMessage {
+    /// The framework asks which keys are held down, when it starts listening
+    /// to key events.
+    GetKeyboardState(()),
+}
+
+#[rustfmt::skip]
+const IDENTITY_TRANSFORM: [f64; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// Converts an index in characters, as the editing state counts them, into
+/// a byte offset into `text`.
+fn byte_offset(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+/// Converts a range of characters into a range of bytes of `text`.
+fn byte_range(text: &str, range: &Range<usize>) -> Range<usize> {
+    byte_offset(text, range.start)..byte_offset(text, range.end)
+}
+
+/// The modifiers [`ModifiersState`] reports with the keys producing them.
+/// The first key is synthesized when the framework doesn't know that the
+/// modifier is held.
+const MODIFIER_KEYS: [(ModifiersState, Key<'static>, [KeyCode; 2]); 4] = [
+    (
+        ModifiersState::SHIFT,
+        Key::Shift,
+        [KeyCode::ShiftLeft, KeyCode::ShiftRight],
+    ),
+    (
+        ModifiersState::CONTROL,
+        Key::Control,
+        [KeyCode::ControlLeft, KeyCode::ControlRight],
+    ),
+    (
+        ModifiersState::ALT,
+        Key::Alt,
+        [KeyCode::AltLeft, KeyCode::AltRight],
+    ),
+    (
+        ModifiersState::SUPER,
+        Key::Super,
+        [KeyCode::SuperLeft, KeyCode::SuperRight],
+    ),
+];
+
+/// What text input needs to know about a key event, as it was when the
+/// event happened.
+#[derive(Debug, Clone, PartialEq)]
+struct TextInputKey {
+    logical_key: Key<'static>,
+    text: Option<&'static str>,
+    state: ElementState,
+    /// The framework answers asynchronously, by then the modifiers might
+    /// have changed already.
+    modifiers: ModifiersState,
+}
+
+/// A key event waiting for the framework to decide whether it handled it.
+struct PendingKeyEvent {
+    id: u64,
+    key: TextInputKey,
+    /// One response is expected for every channel the event was sent on.
+    missing_responses: usize,
+    handled: bool,
+}
+
+/// Key events in the order they were sent to the engine.
+#[derive(Default)]
+struct PendingKeyEvents(VecDeque<PendingKeyEvent>);
+
+impl PendingKeyEvents {
+    fn push(&mut self, id: u64, key: TextInputKey, missing_responses: usize) {
+        self.0.push_back(PendingKeyEvent {
+            id,
+            key,
+            missing_responses,
+            handled: false,
+        });
+    }
+
+    /// Records a response and returns the keys text input gets to see now:
+    /// the ones nobody handled, up to the first one still waiting for a
+    /// response.
+    fn respond(&mut self, id: u64, handled: bool) -> Vec<TextInputKey> {
+        if let Some(pending) = self.0.iter_mut().find(|pending| pending.id == id) {
+            pending.missing_responses -= 1;
+            pending.handled |= handled;
+        }
+        let mut unhandled = vec![];
+        while self
+            .0
+            .front()
+            .map_or(false, |pending| pending.missing_responses == 0)
+        {
+            let pending = self.0.pop_front().unwrap();
+            if !pending.handled {
+                unhandled.push(pending.key);
+            }
+        }
+        unhandled
+    }
+}
+
+/// Routes a response of the framework back to its [`PendingKeyEvent`].
+struct KeyEventResponse {
+    user_data: *const FlutterApplicationUserData,
+    id: u64,
+}
+
+impl KeyEventResponse {
+    fn new(user_data: &FlutterApplicationUserData, id: u64) -> *mut c_void {
+        Box::into_raw(Box::new(Self { user_data, id })) as _
+    }
+
+    fn send(self: Box<Self>, handled: bool) {
+        let user_data = unsafe { &*self.user_data };
+        let id = self.id;
+        user_data
+            .event_loop_proxy
+            .lock()
+            .unwrap()
+            .send_event(user_data.callback(move |application| {
+                application
+                    .keyboard
+                    .key_event_handled(application.engine, id, handled);
+                false
+            }))
+            .ok()
+            .unwrap();
+    }
+}
+
 pub struct Keyboard {
     client: Option<u64>,
     modifiers: ModifiersState,
@@ -36,6 +189,18 @@ pub struct Keyboard {
     clipboard: Arc<Mutex<Clipboard>>,
     input_action: TextInputAction,
     channel: CString,
+    pending_key_events: PendingKeyEvents,
+    next_key_event_id: u64,
+    /// The keys the framework thinks are held down, physical to logical.
+    pressed_keys: HashMap<u64, u64>,
+    /// Set when `modifiers` changed and `pressed_keys` hasn't been checked
+    /// against them yet.
+    modifiers_out_of_sync: bool,
+    /// Maps the coordinates of the text field to the ones of the view, in
+    /// column-major order.
+    editable_transform: [f64; 16],
+    marked_text_rect: Option<TextRect>,
+    caret_rect: Option<TextRect>,
 }
 
 impl Keyboard {
@@ -47,23 +212,155 @@ impl Keyboard {
             clipboard,
             input_action: TextInputAction::Unspecified,
             channel: CString::new(FLUTTER_TEXTINPUT_CHANNEL).unwrap(),
+            pending_key_events: Default::default(),
+            next_key_event_id: 0,
+            pressed_keys: HashMap::new(),
+            modifiers_out_of_sync: false,
+            editable_transform: IDENTITY_TRANSFORM,
+            marked_text_rect: None,
+            caret_rect: None,
         }
     }
+
+    /// winit reports the new modifiers before the key event changing them,
+    /// so they are only compared to the pressed keys in
+    /// [`Self::sync_modifiers`], once the key events are through.
     pub(super) fn modifiers_changed(&mut self, state: ModifiersState) {
         self.modifiers = state;
+        self.modifiers_out_of_sync = true;
     }
 
-    fn move_home(&mut self) {
+    /// Modifiers can change without us seeing the key events, for example
+    /// while another window had the focus. Any difference to the keys the
+    /// framework knows about is fixed with synthesized events. Has to be
+    /// called after all pending window events have been handled.
+    pub(super) fn sync_modifiers(&mut self, engine: FlutterEngine) {
+        if !std::mem::take(&mut self.modifiers_out_of_sync) {
+            return;
+        }
+        for (modifier, key, codes) in MODIFIER_KEYS {
+            // Either key of the modifier counts, the left one is synthesized.
+            let keys: Vec<(u64, u64)> = codes
+                .into_iter()
+                .filter_map(|code| {
+                    Some((
+                        translate_physical_key(code)?,
+                        translate_event_logical_key(key, code)?,
+                    ))
+                })
+                .collect();
+            let pressed: Vec<(u64, u64)> = self
+                .pressed_keys
+                .iter()
+                .filter(|(_, &logical)| keys.iter().any(|&(_, key)| key == logical))
+                .map(|(&physical, &logical)| (physical, logical))
+                .collect();
+            if self.modifiers.contains(modifier) {
+                if !pressed.is_empty() {
+                    continue;
+                }
+                if let Some(&(physical, logical)) = keys.first() {
+                    self.synthesize_key_event(
+                        engine,
+                        FlutterKeyEventType_kFlutterKeyEventTypeDown,
+                        physical,
+                        logical,
+                    );
+                }
+            } else {
+                for (physical, logical) in pressed {
+                    self.synthesize_key_event(
+                        engine,
+                        FlutterKeyEventType_kFlutterKeyEventTypeUp,
+                        physical,
+                        logical,
+                    );
+                }
+            }
+        }
+    }
+
+    /// winit reports the keys that are already held when the window gets the
+    /// focus as synthetic key events, which are forwarded as such. Modifiers
+    /// can still be off, for example if they were pressed before the window
+    /// appeared, so they are checked again.
+    pub(super) fn focus_gained(&mut self) {
+        self.modifiers_out_of_sync = true;
+    }
+
+    /// Releases all keys, as the key up events go to whichever window has the
+    /// focus now.
+    pub(super) fn focus_lost(&mut self, engine: FlutterEngine) {
+        let pressed: Vec<(u64, u64)> = self.pressed_keys.drain().collect();
+        for (physical, logical) in pressed {
+            self.synthesize_key_event(
+                engine,
+                FlutterKeyEventType_kFlutterKeyEventTypeUp,
+                physical,
+                logical,
+            );
+        }
+    }
+
+    /// Sends a key event that has no counterpart on the platform, to bring
+    /// the framework in sync with the actual keyboard state. Neither the
+    /// legacy channel nor text input get to see it.
+    fn synthesize_key_event(
+        &mut self,
+        engine: FlutterEngine,
+        type_: FlutterKeyEventType,
+        physical: u64,
+        logical: u64,
+    ) {
+        if type_ == FlutterKeyEventType_kFlutterKeyEventTypeUp {
+            self.pressed_keys.remove(&physical);
+        } else {
+            self.pressed_keys.insert(physical, logical);
+        }
+        log::debug!(
+            "synthesized keyboard event: type {type_} physical {physical:#x} logical {logical:#x}"
+        );
+        let flutter_event = FlutterKeyEvent {
+            struct_size: size_of::<FlutterKeyEvent>() as _,
+            timestamp: FlutterApplication::current_time() as f64,
+            type_,
+            physical,
+            logical,
+            character: null(),
+            synthesized: true,
+        };
+        FlutterApplication::unwrap_result(unsafe {
+            FlutterEngineSendKeyEvent(engine, &flutter_event, None, null_mut())
+        });
+    }
+
+    /// Returns the encoded success envelope for the reply.
+    pub(super) fn handle_keyboard_message(
+        &self,
+        message: KeyboardMessage,
+    ) -> Result<Vec<u8>, message_codec::Error> {
+        match message {
+            KeyboardMessage::GetKeyboardState(()) => {
+                message_codec::to_success_envelope(&self.pressed_keys)
+            }
+        }
+    }
+
+    pub(super) fn shift_pressed(&self) -> bool {
+        self.modifiers.shift_key()
+    }
+
+    fn move_home(&mut self, shift: bool) {
         self.editing_state.selection_base = Some(0);
-        if !self.modifiers.shift_key() {
+        if !shift {
             self.editing_state.selection_extent = Some(0);
         }
     }
 
-    fn move_end(&mut self) {
+    fn move_end(&mut self, shift: bool) {
         let len = self.editing_state.text.chars().count();
         self.editing_state.selection_extent = Some(len as _);
-        if !self.modifiers.shift_key() {
+        if !shift {
             self.editing_state.selection_base = self.editing_state.selection_extent;
         }
     }
@@ -76,7 +373,8 @@ impl Keyboard {
         let selection = selection_base.min(selection_extent)..selection_base.max(selection_extent);
 
         if len > 0 && selection.start < len {
-            editing_state.text.replace_range(selection.clone(), text);
+            let range = byte_range(&editing_state.text, &selection);
+            editing_state.text.replace_range(range, text);
             editing_state.selection_base = Some((selection.start + text.chars().count()) as _);
         } else {
             editing_state.text.push_str(text);
@@ -85,265 +383,331 @@ impl Keyboard {
         editing_state.selection_extent = editing_state.selection_base;
     }
 
-    pub(super) fn key_event(&mut self, engine: FlutterEngine, event: KeyEvent, synthesized: bool) {
+    /// Sends `event` to the framework through both `FlutterEngineSendKeyEvent`
+    /// and the legacy `flutter/keyevent` channel. Like in the official
+    /// embedders, text input only gets to see the event once both responses
+    /// arrived and neither of them handled it, so shortcuts and focus
+    /// traversal don't also edit the text.
+    pub(super) fn key_event(
+        &mut self,
+        engine: FlutterEngine,
+        user_data: &FlutterApplicationUserData,
+        event: KeyEvent,
+        synthesized: bool,
+    ) {
         log::debug!(
             "keyboard input: logical {:?} physical {:?} (Translated {:?}, {:?})",
             event.logical_key,
             event.physical_key,
-            translate_logical_key(event.logical_key),
+            translate_event_logical_key(event.logical_key, event.physical_key),
             translate_physical_key(event.physical_key),
         );
-        if let (Some(logical), Some(physical)) = (
-            translate_logical_key(event.logical_key),
+        let (mut logical, physical) = match (
+            translate_event_logical_key(event.logical_key, event.physical_key),
             translate_physical_key(event.physical_key),
         ) {
-            // let flutter_event = FlutterKeyboardEvent::Linux {
-            //     r#type: match event.state {
-            //         ElementState::Pressed => FlutterKeyboardEventType::KeyDown,
-            //         ElementState::Released => FlutterKeyboardEventType::KeyUp,
-            //     },
-            //     toolkit: LinuxToolkit::Gtk,
-            //     unicode_scalar_values: if let Some(character) = event.text {
-            //         let mut buffer = [0u8; 8];
-            //         if character.as_bytes().read(&mut buffer).is_ok() {
-            //             u64::from_le_bytes(buffer)
-            //         } else {
-            //             0
-            //         }
-            //     } else {
-            //         0
-            //     },
-            //     key_code: physical,
-            //     scan_code: logical,
-            //     modifiers: 0,
-            //     specified_logical_key: 0,
-            // };
-            // let flutter_event = FlutterKeyboardEvent::Web {
-            //     r#type: match event.state {
-            //         ElementState::Pressed => FlutterKeyboardEventType::KeyDown,
-            //         ElementState::Released => FlutterKeyboardEventType::KeyUp,
-            //     },
-            //     code: event.text.unwrap_or_default().to_owned(),
-            //     key: event.text.unwrap_or_default().to_owned(),
-            //     location: 0,
-            //     meta_state: 0,
-            //     key_code: 0,
-            // };
-
-            // let json = serde_json::to_vec(&flutter_event).unwrap();
-            // log::debug!("keyevent: {:?}", String::from_utf8(json.clone()));
-            // let channel = CStr::from_bytes_with_nul(b"flutter/keyevent\0").unwrap();
-            // let message = FlutterPlatformMessage {
-            //     struct_size: size_of::<FlutterPlatformMessage>() as _,
-            //     channel: channel.as_ptr(),
-            //     message: json.as_ptr(),
-            //     message_size: json.len() as _,
-            //     response_handle: null(),
-            // };
-
-            // Self::unwrap_result(unsafe { FlutterEngineSendPlatformMessage(self.engine, &message) });
-
-            // drop(message);
-            // drop(channel);
-
-            let type_ = match event.state {
-                ElementState::Pressed => {
-                    if event.repeat {
-                        FlutterKeyEventType_kFlutterKeyEventTypeRepeat
-                    } else {
-                        FlutterKeyEventType_kFlutterKeyEventTypeDown
-                    }
+            (Some(logical), Some(physical)) => (logical, physical),
+            _ => return,
+        };
+        let type_ = match event.state {
+            // The framework might already know about the key from a
+            // synthesized event.
+            ElementState::Pressed if event.repeat || self.pressed_keys.contains_key(&physical) => {
+                logical = *self.pressed_keys.entry(physical).or_insert(logical);
+                FlutterKeyEventType_kFlutterKeyEventTypeRepeat
+            }
+            ElementState::Pressed => {
+                self.pressed_keys.insert(physical, logical);
+                FlutterKeyEventType_kFlutterKeyEventTypeDown
+            }
+            ElementState::Released => match self.pressed_keys.remove(&physical) {
+                // Releasing a modifier like AltGr before the key changes its
+                // logical key, but the framework expects the one it was
+                // pressed with.
+                Some(pressed_logical) => {
+                    logical = pressed_logical;
+                    FlutterKeyEventType_kFlutterKeyEventTypeUp
+                }
+                // Already released by a synthesized event.
+                None => return,
+            },
+        };
+        let id = self.next_key_event_id;
+        self.next_key_event_id += 1;
+        log::debug!(
+            "keyboard event: physical {physical:#x} logical {logical:#x} text {:?}",
+            event.text
+        );
+        let character = event.text.map(|text| CString::new(text).unwrap());
+        let flutter_event = FlutterKeyEvent {
+            struct_size: size_of::<FlutterKeyEvent>() as _,
+            timestamp: FlutterApplication::current_time() as f64,
+            type_,
+            physical,
+            logical,
+            character: if event.state == ElementState::Released {
+                null()
+            } else if let Some(character) = &character {
+                character.as_ptr()
+            } else {
+                null()
+            },
+            synthesized,
+        };
+        FlutterApplication::unwrap_result(unsafe {
+            FlutterEngineSendKeyEvent(
+                engine,
+                &flutter_event,
+                Some(Self::key_data_response),
+                KeyEventResponse::new(user_data, id),
+            )
+        });
+        drop(character);
+        let mut missing_responses = 1;
+
+        if let Some(message) = FlutterKeyboardEvent::gtk(&event, self.modifiers, logical) {
+            Self::send_legacy_key_event(engine, message, KeyEventResponse::new(user_data, id));
+            missing_responses += 1;
+        }
+        let key = TextInputKey {
+            logical_key: event.logical_key,
+            text: event.text,
+            state: event.state,
+            modifiers: self.modifiers,
+        };
+        self.pending_key_events.push(id, key, missing_responses);
+    }
+
+    /// Sends the event to `RawKeyboard` in the framework.
+    fn send_legacy_key_event(
+        engine: FlutterEngine,
+        message: FlutterKeyboardEvent,
+        response: *mut c_void,
+    ) {
+        let message = serde_json::to_vec(&message).unwrap();
+        let channel = CString::new(FLUTTER_KEYEVENT_CHANNEL).unwrap();
+        let mut response_handle = null_mut();
+        FlutterApplication::unwrap_result(unsafe {
+            FlutterPlatformMessageCreateResponseHandle(
+                engine,
+                Some(Self::key_event_response),
+                response,
+                &mut response_handle,
+            )
+        });
+        let platform_message = FlutterPlatformMessage {
+            struct_size: size_of::<FlutterPlatformMessage>() as _,
+            channel: channel.as_ptr(),
+            message: message.as_ptr(),
+            message_size: message.len() as _,
+            response_handle,
+        };
+        FlutterApplication::unwrap_result(unsafe {
+            FlutterEngineSendPlatformMessage(engine, &platform_message)
+        });
+        FlutterApplication::unwrap_result(unsafe {
+            FlutterPlatformMessageReleaseResponseHandle(engine, response_handle)
+        });
+        drop(platform_message);
+        drop(channel);
+    }
+
+    extern "C" fn key_data_response(handled: bool, user_data: *mut c_void) {
+        unsafe { Box::from_raw(user_data as *mut KeyEventResponse) }.send(handled);
+    }
+
+    extern "C" fn key_event_response(data: *const u8, size: usize, user_data: *mut c_void) {
+        let response = unsafe { Box::from_raw(user_data as *mut KeyEventResponse) };
+        // No response means that nobody listens on the channel.
+        let handled = size > 0
+            && match serde_json::from_slice::<FlutterKeyboardEventResponse>(unsafe {
+                std::slice::from_raw_parts(data, size)
+            }) {
+                Ok(response) => response.handled,
+                Err(err) => {
+                    log::error!("Invalid {FLUTTER_KEYEVENT_CHANNEL} response: {err}");
+                    false
                 }
-                ElementState::Released => FlutterKeyEventType_kFlutterKeyEventTypeUp,
-            };
-            log::debug!(
-                "keyboard event: physical {physical:#x} logical {logical:#x} text {:?}",
-                event.text
-            );
-            let character = event.text.map(|text| CString::new(text).unwrap());
-            let flutter_event = FlutterKeyEvent {
-                struct_size: size_of::<FlutterKeyEvent>() as _,
-                timestamp: FlutterApplication::current_time() as f64,
-                type_,
-                physical,
-                logical,
-                character: if event.state == ElementState::Released {
-                    null()
-                } else if let Some(character) = &character {
-                    character.as_ptr()
-                } else {
-                    null()
-                },
-                synthesized,
             };
-            FlutterApplication::unwrap_result(unsafe {
-                FlutterEngineSendKeyEvent(engine, &flutter_event, None, null_mut())
-            });
-            drop(character);
+        response.send(handled);
+    }
 
-            log::debug!(
-                "Updating editing state for keyboard client {:?}",
-                self.client
-            );
+    /// Records a response of the framework. Events that weren't handled are
+    /// passed on to text input, in the order they arrived.
+    pub(super) fn key_event_handled(&mut self, engine: FlutterEngine, id: u64, handled: bool) {
+        for key in self.pending_key_events.respond(id, handled) {
+            self.text_input_key_event(engine, &key);
+        }
+    }
 
-            if event.state == ElementState::Pressed
-                && self
-                    .editing_state
-                    .selection_base
-                    .map(|val| val >= 0)
-                    .unwrap_or(false)
-                && self
-                    .editing_state
-                    .selection_extent
-                    .map(|val| val >= 0)
-                    .unwrap_or(false)
+    /// Applies a key press the framework didn't handle to the editing state
+    /// of the text input client.
+    fn text_input_key_event(&mut self, engine: FlutterEngine, key: &TextInputKey) {
+        log::debug!(
+            "Updating editing state for keyboard client {:?}",
+            self.client
+        );
+        if key.state == ElementState::Pressed
+            && self
+                .editing_state
+                .selection_base
+                .map(|val| val >= 0)
+                .unwrap_or(false)
+            && self
+                .editing_state
+                .selection_extent
+                .map(|val| val >= 0)
+                .unwrap_or(false)
+        {
+            // send flutter/textinput message
             {
-                // send flutter/textinput message
-                {
-                    let editing_state = &mut self.editing_state;
-                    let len = editing_state.text.chars().count();
-                    let selection_base = editing_state.selection_base.unwrap_or(0) as usize;
-                    let selection_extent = editing_state.selection_extent.unwrap_or(0) as usize;
-                    let selection =
-                        selection_base.min(selection_extent)..selection_base.max(selection_extent);
-                    match event.logical_key {
-                        #[cfg(any(target_os = "macos", target_os = "ios"))]
-                        Key::ArrowLeft if self.keyboard_modifiers.meta_key() => {
-                            self.move_home();
-                        }
-                        #[cfg(any(target_os = "macos", target_os = "ios"))]
-                        Key::ArrowRight if self.keyboard_modifiers.meta_key() => {
-                            self.move_end();
-                        }
-                        Key::ArrowLeft => {
-                            if selection.start > 0 {
-                                if !self.modifiers.shift_key() && selection.start != selection.end {
+                let editing_state = &mut self.editing_state;
+                let len = editing_state.text.chars().count();
+                let selection_base = editing_state.selection_base.unwrap_or(0) as usize;
+                let selection_extent = editing_state.selection_extent.unwrap_or(0) as usize;
+                let selection =
+                    selection_base.min(selection_extent)..selection_base.max(selection_extent);
+                match key.logical_key {
+                    #[cfg(any(target_os = "macos", target_os = "ios"))]
+                    Key::ArrowLeft if key.modifiers.meta_key() => {
+                        self.move_home(key.modifiers.shift_key());
+                    }
+                    #[cfg(any(target_os = "macos", target_os = "ios"))]
+                    Key::ArrowRight if key.modifiers.meta_key() => {
+                        self.move_end(key.modifiers.shift_key());
+                    }
+                    Key::ArrowLeft => {
+                        if selection.start > 0 {
+                            if !key.modifiers.shift_key() && selection.start != selection.end {
+                                editing_state.selection_extent = editing_state.selection_base;
+                            } else {
+                                editing_state.selection_base = Some((selection.start - 1) as _);
+                                if !key.modifiers.shift_key() {
                                     editing_state.selection_extent = editing_state.selection_base;
-                                } else {
-                                    editing_state.selection_base = Some((selection.start - 1) as _);
-                                    if !self.modifiers.shift_key() {
-                                        editing_state.selection_extent =
-                                            editing_state.selection_base;
-                                    }
                                 }
-                            } else if !self.modifiers.shift_key()
-                                && selection.start != selection.end
-                            {
-                                editing_state.selection_extent = editing_state.selection_base;
                             }
+                        } else if !key.modifiers.shift_key() && selection.start != selection.end {
+                            editing_state.selection_extent = editing_state.selection_base;
                         }
-                        Key::ArrowRight => {
-                            if selection.end < len {
-                                if !self.modifiers.shift_key() && selection.start != selection.end {
-                                    editing_state.selection_base = editing_state.selection_extent;
-                                } else {
-                                    editing_state.selection_extent = Some((selection.end + 1) as _);
-                                    if !self.modifiers.shift_key() {
-                                        editing_state.selection_base =
-                                            editing_state.selection_extent;
-                                    }
-                                }
-                            } else if !self.modifiers.shift_key()
-                                && selection.start != selection.end
-                            {
+                    }
+                    Key::ArrowRight => {
+                        if selection.end < len {
+                            if !key.modifiers.shift_key() && selection.start != selection.end {
                                 editing_state.selection_base = editing_state.selection_extent;
-                            }
-                        }
-                        Key::ArrowUp | Key::Home => {
-                            self.move_home();
-                        }
-                        Key::ArrowDown | Key::End => {
-                            self.move_end();
-                        }
-                        Key::Backspace => {
-                            if selection.start == selection.end {
-                                if selection.start > 0 {
-                                    editing_state.text.remove(selection.start - 1);
-                                    editing_state.selection_base = Some((selection.start - 1) as _);
-                                }
-                                editing_state.selection_extent = editing_state.selection_base;
                             } else {
-                                editing_state.text.replace_range(selection.clone(), "");
-                                editing_state.selection_extent = editing_state.selection_base;
-                            }
-                        }
-                        Key::Delete => {
-                            if selection.start == selection.end {
-                                if selection.start < len {
-                                    editing_state.text.remove(selection.start);
+                                editing_state.selection_extent = Some((selection.end + 1) as _);
+                                if !key.modifiers.shift_key() {
+                                    editing_state.selection_base = editing_state.selection_extent;
                                 }
-                            } else {
-                                editing_state.text.replace_range(selection.clone(), "");
-                                editing_state.selection_extent = editing_state.selection_base;
                             }
+                        } else if !key.modifiers.shift_key() && selection.start != selection.end {
+                            editing_state.selection_base = editing_state.selection_extent;
                         }
-                        Key::Character("a") if self.modifiers.action_key() => {
-                            editing_state.selection_base = Some(0);
-                            editing_state.selection_extent = Some(len as _);
-                        }
-                        #[cfg(any(target_os = "macos", target_os = "ios"))]
-                        Key::Character("a") if self.keyboard_modifiers.control_key() => {
-                            self.move_home();
-                        }
-                        #[cfg(any(target_os = "macos", target_os = "ios"))]
-                        Key::Character("e") if self.modifiers.control_key() => {
-                            self.move_end();
-                        }
-                        Key::Character("x") if self.modifiers.action_key() => {
-                            if selection.start != selection.end {
-                                let text = editing_state
+                    }
+                    Key::ArrowUp | Key::Home => {
+                        self.move_home(key.modifiers.shift_key());
+                    }
+                    Key::ArrowDown | Key::End => {
+                        self.move_end(key.modifiers.shift_key());
+                    }
+                    Key::Backspace => {
+                        if selection.start == selection.end {
+                            if selection.start > 0 {
+                                editing_state
                                     .text
-                                    .chars()
-                                    .skip(selection.start)
-                                    .take(selection.end - selection.start)
-                                    .collect();
-                                editing_state.text.replace_range(selection.clone(), "");
-                                editing_state.selection_extent = editing_state.selection_base;
-                                self.clipboard.lock().unwrap().set_text(text).unwrap();
+                                    .remove(byte_offset(&editing_state.text, selection.start - 1));
+                                editing_state.selection_base = Some((selection.start - 1) as _);
                             }
+                            editing_state.selection_extent = editing_state.selection_base;
+                        } else {
+                            let range = byte_range(&editing_state.text, &selection);
+                            editing_state.text.replace_range(range, "");
+                            editing_state.selection_base = Some(selection.start as _);
+                            editing_state.selection_extent = editing_state.selection_base;
                         }
-                        Key::Character("c") if self.modifiers.action_key() => {
-                            if selection.start != selection.end {
-                                let text = editing_state
+                    }
+                    Key::Delete => {
+                        if selection.start == selection.end {
+                            if selection.start < len {
+                                editing_state
                                     .text
-                                    .chars()
-                                    .skip(selection.start)
-                                    .take(selection.end - selection.start)
-                                    .collect();
-                                self.clipboard.lock().unwrap().set_text(text).unwrap();
+                                    .remove(byte_offset(&editing_state.text, selection.start));
                             }
+                        } else {
+                            let range = byte_range(&editing_state.text, &selection);
+                            editing_state.text.replace_range(range, "");
+                            editing_state.selection_base = Some(selection.start as _);
+                            editing_state.selection_extent = editing_state.selection_base;
                         }
-                        Key::Character("v") if self.modifiers.action_key() => {
-                            let text = {
-                                let mut clipboard = self.clipboard.lock().unwrap();
-                                clipboard.get_text()
-                            };
-                            if let Ok(text) = text {
-                                self.insert_text(&text);
-                            }
+                    }
+                    Key::Character("a") if key.modifiers.action_key() => {
+                        editing_state.selection_base = Some(0);
+                        editing_state.selection_extent = Some(len as _);
+                    }
+                    #[cfg(any(target_os = "macos", target_os = "ios"))]
+                    Key::Character("a") if key.modifiers.control_key() => {
+                        self.move_home(key.modifiers.shift_key());
+                    }
+                    #[cfg(any(target_os = "macos", target_os = "ios"))]
+                    Key::Character("e") if key.modifiers.control_key() => {
+                        self.move_end(key.modifiers.shift_key());
+                    }
+                    Key::Character("x") if key.modifiers.action_key() => {
+                        if selection.start != selection.end {
+                            let text = editing_state
+                                .text
+                                .chars()
+                                .skip(selection.start)
+                                .take(selection.end - selection.start)
+                                .collect();
+                            let range = byte_range(&editing_state.text, &selection);
+                            editing_state.text.replace_range(range, "");
+                            editing_state.selection_base = Some(selection.start as _);
+                            editing_state.selection_extent = editing_state.selection_base;
+                            self.clipboard.lock().unwrap().set_text(text).unwrap();
                         }
-                        Key::Enter => {
-                            self.send_action(engine, self.input_action);
+                    }
+                    Key::Character("c") if key.modifiers.action_key() => {
+                        if selection.start != selection.end {
+                            let text = editing_state
+                                .text
+                                .chars()
+                                .skip(selection.start)
+                                .take(selection.end - selection.start)
+                                .collect();
+                            self.clipboard.lock().unwrap().set_text(text).unwrap();
                         }
-                        Key::Tab => {
-                            if self.modifiers.shift_key() {
-                                self.send_action(engine, TextInputAction::Previous);
-                            } else {
-                                self.send_action(engine, TextInputAction::Next);
-                            }
+                    }
+                    Key::Character("v") if key.modifiers.action_key() => {
+                        let text = {
+                            let mut clipboard = self.clipboard.lock().unwrap();
+                            clipboard.get_text()
+                        };
+                        if let Ok(text) = text {
+                            self.insert_text(&text);
                         }
-                        _ if self.modifiers.control_key() || self.modifiers.super_key() => {
-                            // ignore
+                    }
+                    Key::Enter => {
+                        self.send_action(engine, self.input_action);
+                    }
+                    Key::Tab => {
+                        if key.modifiers.shift_key() {
+                            self.send_action(engine, TextInputAction::Previous);
+                        } else {
+                            self.send_action(engine, TextInputAction::Next);
                         }
-                        _ => {
-                            if let Some(text) = event.text {
-                                self.insert_text(text);
-                            }
+                    }
+                    _ if key.modifiers.control_key() || key.modifiers.super_key() => {
+                        // ignore
+                    }
+                    _ => {
+                        if let Some(text) = key.text {
+                            self.insert_text(text);
                         }
                     }
                 }
-                self.update_editing_state(engine);
             }
+            self.update_editing_state(engine);
         }
     }
 
@@ -386,17 +750,128 @@ impl Keyboard {
         }
     }
 
-    pub(super) fn handle_textinput_message(&mut self, textinput: TextInput) {
+    fn is_composing(&self) -> bool {
+        self.editing_state
+            .composing_base
+            .map_or(false, |base| base >= 0)
+    }
+
+    /// Replaces the text being composed, or the selection if there is none,
+    /// with `text`. Returns where the text starts.
+    fn replace_composing(&mut self, text: &str) -> usize {
+        let editing_state = &mut self.editing_state;
+        let len = editing_state.text.chars().count();
+        let (base, extent) = match (editing_state.composing_base, editing_state.composing_extent) {
+            (Some(base), Some(extent)) if base >= 0 && extent >= base => (base, extent),
+            _ => (
+                editing_state.selection_base.unwrap_or(len as _),
+                editing_state.selection_extent.unwrap_or(len as _),
+            ),
+        };
+        let start = (base.min(extent).max(0) as usize).min(len);
+        let end = (base.max(extent).max(0) as usize).min(len);
+        let range = byte_range(&editing_state.text, &(start..end));
+        editing_state.text.replace_range(range, text);
+        editing_state.selection_base = Some((start + text.chars().count()) as _);
+        editing_state.selection_extent = editing_state.selection_base;
+        start
+    }
+
+    /// Applies input method events to the editing state. The text being
+    /// composed is already part of the text, marked by the composing range.
+    pub(super) fn ime_event(&mut self, engine: FlutterEngine, window: &Window, ime: Ime) {
+        if self.client.is_none() {
+            return;
+        }
+        log::debug!("IME event: {ime:?}");
+        match ime {
+            Ime::Enabled => return,
+            Ime::Preedit(text, cursor) => {
+                let start = self.replace_composing(&text);
+                let editing_state = &mut self.editing_state;
+                if text.is_empty() {
+                    editing_state.composing_base = Some(-1);
+                    editing_state.composing_extent = Some(-1);
+                } else {
+                    editing_state.composing_base = Some(start as _);
+                    editing_state.composing_extent = Some((start + text.chars().count()) as _);
+                    // The cursor is given as byte offsets into the preedit.
+                    if let Some((cursor_start, cursor_end)) = cursor {
+                        let offset = |byte: usize| {
+                            (start + text.get(..byte).unwrap_or(&text).chars().count()) as i64
+                        };
+                        editing_state.selection_base = Some(offset(cursor_start));
+                        editing_state.selection_extent = Some(offset(cursor_end));
+                    }
+                }
+            }
+            Ime::Commit(text) => {
+                self.replace_composing(&text);
+                self.editing_state.composing_base = Some(-1);
+                self.editing_state.composing_extent = Some(-1);
+            }
+            Ime::Disabled if !self.is_composing() => return,
+            Ime::Disabled => {
+                // The text that was being composed is dropped.
+                self.replace_composing("");
+                self.editing_state.composing_base = Some(-1);
+                self.editing_state.composing_extent = Some(-1);
+            }
+        }
+        self.update_editing_state(engine);
+        self.update_ime_position(window);
+    }
+
+    /// Moves the candidate window of the input method below the text being
+    /// composed, or below the caret.
+    fn update_ime_position(&self, window: &Window) {
+        let rect = match (self.is_composing(), self.marked_text_rect, self.caret_rect) {
+            (true, Some(rect), _) | (_, _, Some(rect)) => rect,
+            _ => return,
+        };
+        let (x, y) = (rect.x, rect.y + rect.height);
+        let transform = &self.editable_transform;
+        let w = transform[3] * x + transform[7] * y + transform[15];
+        let scale_factor = window.scale_factor();
+        window.set_ime_position(PhysicalPosition::new(
+            (transform[0] * x + transform[4] * y + transform[12]) / w * scale_factor,
+            (transform[1] * x + transform[5] * y + transform[13]) / w * scale_factor,
+        ));
+    }
+
+    pub(super) fn handle_textinput_message(&mut self, window: &Window, textinput: TextInput) {
         match textinput {
             TextInput::SetClient(client_id, parameters) => {
                 self.client = Some(client_id);
                 self.input_action = parameters.input_action;
+                window.set_ime_allowed(true);
                 log::debug!("Setting keyboard client to {:?}", client_id);
             }
             TextInput::ClearClient => {
                 self.client = None;
+                self.marked_text_rect = None;
+                self.caret_rect = None;
+                window.set_ime_allowed(false);
                 log::debug!("Setting keyboard client to None");
             }
+            TextInput::Show => {
+                window.set_ime_allowed(self.client.is_some());
+            }
+            TextInput::Hide => {
+                window.set_ime_allowed(false);
+            }
+            TextInput::SetEditableSizeAndTransform(size_and_transform) => {
+                self.editable_transform = size_and_transform.transform;
+                self.update_ime_position(window);
+            }
+            TextInput::SetMarkedTextRect(rect) => {
+                self.marked_text_rect = Some(rect);
+                self.update_ime_position(window);
+            }
+            TextInput::SetCaretRect(rect) => {
+                self.caret_rect = Some(rect);
+                self.update_ime_position(window);
+            }
             TextInput::SetEditingState(state) => {
                 log::debug!("set editing state: {:#?}", state);
                 self.editing_state = state;
@@ -407,3 +882,45 @@ impl Keyboard {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &'static str, modifiers: ModifiersState) -> TextInputKey {
+        TextInputKey {
+            logical_key: Key::Character(text),
+            text: Some(text),
+            state: ElementState::Pressed,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn responses_keep_the_modifiers_of_their_event() {
+        let mut pending = PendingKeyEvents::default();
+        // Shift is released before the framework answers the first event.
+        pending.push(0, key("A", ModifiersState::SHIFT), 2);
+        pending.push(1, key("b", ModifiersState::empty()), 2);
+
+        assert!(pending.respond(1, false).is_empty());
+        assert!(pending.respond(1, false).is_empty());
+        assert!(pending.respond(0, false).is_empty());
+        assert_eq!(
+            pending.respond(0, false),
+            [
+                key("A", ModifiersState::SHIFT),
+                key("b", ModifiersState::empty())
+            ]
+        );
+    }
+
+    #[test]
+    fn handled_events_are_not_returned() {
+        let mut pending = PendingKeyEvents::default();
+        pending.push(0, key("a", ModifiersState::CONTROL), 2);
+        assert!(pending.respond(0, true).is_empty());
+        assert!(pending.respond(0, false).is_empty());
+        assert!(pending.0.is_empty());
+    }
+}
@@ -1,4 +1,16 @@
 use serde::{Deserialize, Serialize};
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState},
+};
+
+use crate::keyboard_gtk_key_map::{dead_key_keysym, translate_gtk_key};
+
+// Modifier masks of GDK, as the framework's `GtkKeyHelper` expects them.
+const GTK_SHIFT_MASK: u64 = 1 << 0;
+const GTK_CONTROL_MASK: u64 = 1 << 2;
+const GTK_MOD1_MASK: u64 = 1 << 3;
+const GTK_META_MASK: u64 = 1 << 28;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -17,6 +29,7 @@ pub(super) enum FlutterKeyboardEventType {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase", tag = "keymap")]
 pub(super) enum FlutterKeyboardEvent {
+    #[serde(rename_all = "camelCase")]
     Android {
         r#type: FlutterKeyboardEventType,
         /// The current set of additional flags for this event.
@@ -105,6 +118,7 @@ pub(super) enum FlutterKeyboardEvent {
         /// for more information.
         repeat_count: u64,
     },
+    #[serde(rename_all = "camelCase")]
     Macos {
         r#type: FlutterKeyboardEventType,
         /// The Unicode characters associated with a key-up or key-down event.
@@ -134,6 +148,7 @@ pub(super) enum FlutterKeyboardEvent {
         modifiers: u64,
         specified_logical_key: u64,
     },
+    #[serde(rename_all = "camelCase")]
     Ios {
         r#type: FlutterKeyboardEventType,
         /// The Unicode characters associated with a key-up or key-down event.
@@ -162,6 +177,7 @@ pub(super) enum FlutterKeyboardEvent {
         ///  * [Apple's UIKey documentation](https://developer.apple.com/documentation/uikit/uikey/3526133-modifierflags?language=objc)
         modifiers: u64,
     },
+    #[serde(rename_all = "camelCase")]
     Linux {
         r#type: FlutterKeyboardEventType,
         /// There is no real concept of a "native" window toolkit on Linux, and each implementation
@@ -194,8 +210,10 @@ pub(super) enum FlutterKeyboardEvent {
         /// logical keys in a way that can not be derived from per-key information.
         ///
         /// This is not part of the native GTK key event.
-        specified_logical_key: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        specified_logical_key: Option<u64>,
     },
+    #[serde(rename_all = "camelCase")]
     Windows {
         r#type: FlutterKeyboardEventType,
         /// The Unicode code point represented by the key event, if any.
@@ -216,6 +234,7 @@ pub(super) enum FlutterKeyboardEvent {
         /// the ones defined in https://github.com/flutter/engine/blob/master/shell/platform/windows/key_event_handler.cc
         modifiers: u64,
     },
+    #[serde(rename_all = "camelCase")]
     Web {
         r#type: FlutterKeyboardEventType,
         /// The `KeyboardEvent.code` corresponding to this event.
@@ -256,3 +275,65 @@ pub(super) enum FlutterKeyboardEvent {
         key_code: u64,
     },
 }
+
+/// The framework's reply to a `flutter/keyevent` message.
+#[derive(Debug, Deserialize)]
+pub(super) struct FlutterKeyboardEventResponse {
+    pub handled: bool,
+}
+
+/// The keysym X11 assigns to a character.
+fn char_to_keysym(c: char) -> u64 {
+    match c as u32 {
+        // Latin-1 maps directly.
+        c @ (0x20..=0x7e | 0xa0..=0xff) => c as u64,
+        c => 0x0100_0000 + c as u64,
+    }
+}
+
+impl FlutterKeyboardEvent {
+    /// Builds the message the GTK embedder would send for `event`. `logical`
+    /// is passed as the specified logical key, so the framework sees the same
+    /// key as through `FlutterEngineSendKeyEvent`.
+    pub(super) fn gtk(event: &KeyEvent, modifiers: ModifiersState, logical: u64) -> Option<Self> {
+        let (scan_code, physical_keysym) = translate_gtk_key(event.physical_key)?;
+        let character = match event.logical_key {
+            Key::Character(character) => Some(character),
+            _ => None,
+        };
+        let key_code = match event.logical_key {
+            Key::Dead(Some(c)) => dead_key_keysym(c),
+            _ => character
+                .and_then(|character| character.chars().next())
+                .map(char_to_keysym),
+        }
+        .unwrap_or(physical_keysym);
+        let unicode_scalar_values = character
+            .or(event.text)
+            .and_then(|text| text.chars().next())
+            .map_or(0, |c| c as u64);
+        let mut gtk_modifiers = 0;
+        for (pressed, mask) in [
+            (modifiers.shift_key(), GTK_SHIFT_MASK),
+            (modifiers.control_key(), GTK_CONTROL_MASK),
+            (modifiers.alt_key(), GTK_MOD1_MASK),
+            (modifiers.super_key(), GTK_META_MASK),
+        ] {
+            if pressed {
+                gtk_modifiers |= mask;
+            }
+        }
+        Some(Self::Linux {
+            r#type: match event.state {
+                ElementState::Pressed => FlutterKeyboardEventType::KeyDown,
+                ElementState::Released => FlutterKeyboardEventType::KeyUp,
+            },
+            toolkit: LinuxToolkit::Gtk,
+            unicode_scalar_values,
+            key_code,
+            scan_code,
+            modifiers: gtk_modifiers,
+            specified_logical_key: Some(logical),
+        })
+    }
+}
@@ -7,7 +7,7 @@ use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::FromPrimitive;
 use serde::{
     de::{self, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess},
-    Deserialize,
+    ser, Deserialize, Serialize,
 };
 
 #[derive(Debug)]
@@ -19,6 +19,7 @@ pub enum Error {
     InvalidFieldType,
     TrailingCharacters,
     Eof,
+    UnknownLength,
     Message(String),
 }
 
@@ -28,6 +29,12 @@ impl de::Error for Error {
     }
 }
 
+impl ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
 impl From<TryFromIntError> for Error {
     fn from(err: TryFromIntError) -> Self {
         Self::ValueOutOfRange(err)
@@ -51,6 +58,7 @@ impl std::fmt::Display for Error {
             Error::Message(msg) => formatter.write_str(msg),
             Error::Eof => formatter.write_str("unexpected end of input"),
             Error::TrailingCharacters => formatter.write_str("trailing characters in input"),
+            Error::UnknownLength => formatter.write_str("sequence without a known length"),
             /* and so forth */
         }
     }
@@ -698,3 +706,429 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
         de::Deserializer::deserialize_map(self.de, visitor)
     }
 }
+
+pub struct Serializer {
+    output: Vec<u8>,
+}
+
+/// Encodes `value` as a standalone message.
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer { output: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Encodes `value` as the successful result of a method call.
+pub fn to_success_envelope<T>(value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    // Alignment is relative to the start of the message, so the envelope byte
+    // has to be in the buffer already.
+    let mut serializer = Serializer { output: vec![0] };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+impl Serializer {
+    fn write_field_type(&mut self, field: FlutterStandardField) {
+        self.output.push(field as u8);
+    }
+
+    fn write_size(&mut self, size: usize) -> Result<(), Error> {
+        if size < 254 {
+            self.output.push(size as _);
+        } else if let Ok(size) = u16::try_from(size) {
+            self.output.push(254);
+            self.output.extend_from_slice(&size.to_le_bytes());
+        } else {
+            self.output.push(255);
+            self.output
+                .extend_from_slice(&u32::try_from(size)?.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn write_alignment(&mut self, alignment: usize) {
+        let offset = self.output.len() % alignment;
+        if offset > 0 {
+            self.output
+                .resize(self.output.len() + alignment - offset, 0);
+        }
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(if v {
+            FlutterStandardField::True
+        } else {
+            FlutterStandardField::False
+        });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(FlutterStandardField::Int32);
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        // Like the framework, use the smallest representation that fits.
+        if let Ok(v) = i32::try_from(v) {
+            return self.serialize_i32(v);
+        }
+        self.write_field_type(FlutterStandardField::Int64);
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.try_into()?)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(FlutterStandardField::Float64);
+        self.write_alignment(size_of::<f64>());
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(FlutterStandardField::String);
+        self.write_size(v.len())?;
+        self.output.extend_from_slice(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(FlutterStandardField::UInt8Data);
+        self.write_size(v.len())?;
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.write_field_type(FlutterStandardField::Nil);
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    // Enums are method calls: the name of the method followed by its
+    // arguments, the same way they are deserialized.
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.serialize_str(variant)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.write_field_type(FlutterStandardField::List);
+        self.write_size(len.ok_or(Error::UnknownLength)?)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.serialize_str(variant)?;
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.write_field_type(FlutterStandardField::Map);
+        self.write_size(len.ok_or(Error::UnknownLength)?)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        self.serialize_str(variant)?;
+        self.serialize_map(Some(len))
+    }
+}
+
+impl<'a> ser::SerializeSeq for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::Serializer::serialize_str(&mut **self, key)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::Serializer::serialize_str(&mut **self, key)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn serializes_scalars() {
+        assert_eq!(to_vec(&()).unwrap(), [0]);
+        assert_eq!(to_vec(&true).unwrap(), [1]);
+        assert_eq!(to_vec(&false).unwrap(), [2]);
+        assert_eq!(to_vec(&-2i64).unwrap(), [3, 0xfe, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            to_vec(&0x1_0000_0000u64).unwrap(),
+            [4, 0, 0, 0, 0, 1, 0, 0, 0]
+        );
+        assert_eq!(to_vec("hi").unwrap(), [7, 2, b'h', b'i']);
+        assert_eq!(to_vec(&Some(7u8)).unwrap(), [3, 7, 0, 0, 0]);
+        assert_eq!(to_vec(&None::<u8>).unwrap(), [0]);
+    }
+
+    #[test]
+    fn aligns_doubles_to_the_message() {
+        let mut expected = vec![0, 6, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(&1.5f64.to_le_bytes());
+        assert_eq!(to_success_envelope(&1.5f64).unwrap(), expected);
+    }
+
+    #[test]
+    fn serializes_long_sizes() {
+        let long = "a".repeat(300);
+        let encoded = to_vec(long.as_str()).unwrap();
+        assert_eq!(encoded[..4], [7, 254, 0x2c, 0x01]);
+        assert_eq!(encoded.len(), 4 + 300);
+    }
+
+    #[test]
+    fn round_trips_collections() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), vec![1i32, 2, 3]);
+        map.insert("b".to_owned(), vec![]);
+        let encoded = to_vec(&map).unwrap();
+        assert_eq!(encoded[..2], [13, 2]);
+        assert_eq!(
+            from_slice::<BTreeMap<String, Vec<i32>>>(&encoded).unwrap(),
+            map
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use wgpu::{
+    CommandEncoder, Device, Extent3d, ImageCopyTexture, ImageCopyTextureBase, ImageDataLayout,
+    Origin3d, Queue, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
+
+use super::{damage::DamageRect, FlutterApplication};
+
+const HUD_WIDTH: u32 = 160;
+const HUD_HEIGHT: u32 = 126;
+/// Every pixel of the font is drawn as a square of this size.
+const FONT_SCALE: u32 = 2;
+const GLYPH_ADVANCE: u32 = 4 * FONT_SCALE;
+const LINE_HEIGHT: u32 = 7 * FONT_SCALE;
+const PADDING: u32 = 4;
+/// BGRA
+const BACKGROUND: [u8; 4] = [0x20, 0x20, 0x20, 0xff];
+const FOREGROUND: [u8; 4] = [0x40, 0xff, 0x40, 0xff];
+
+/// A 3x5 pixel font covering the characters the HUD needs. Every row is
+/// encoded in the lowest three bits, the leftmost pixel being the highest.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0; 5],
+    }
+}
+
+/// The frame [`PerfHud::draw`] draws on.
+pub(super) struct PerfHudFrame<'a> {
+    pub target: &'a Texture,
+    pub target_size: (u32, u32),
+    pub layers: usize,
+    pub present_start: u64,
+}
+
+/// Collects frame statistics and draws them on top of the Flutter layers.
+///
+/// All timestamps are in nanoseconds, as returned by
+/// [`FlutterApplication::current_time`].
+pub(super) struct PerfHud {
+    enabled: AtomicBool,
+    /// Whether the HUD was part of the last frame.
+    drawn: AtomicBool,
+    last_vsync: AtomicU64,
+    raster_start: AtomicU64,
+    last_present_duration: AtomicU64,
+    backing_stores: AtomicUsize,
+    backing_store_bytes: AtomicUsize,
+    platform_queue_depth: AtomicUsize,
+    /// Pointer events and the calls used to send them, since the last draw.
+    pointer_events: AtomicUsize,
+    pointer_batches: AtomicUsize,
+    texture: Mutex<Option<Texture>>,
+}
+
+impl PerfHud {
+    pub(super) fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            drawn: AtomicBool::new(false),
+            last_vsync: AtomicU64::new(0),
+            raster_start: AtomicU64::new(0),
+            last_present_duration: AtomicU64::new(0),
+            backing_stores: AtomicUsize::new(0),
+            backing_store_bytes: AtomicUsize::new(0),
+            platform_queue_depth: AtomicUsize::new(0),
+            pointer_events: AtomicUsize::new(0),
+            pointer_batches: AtomicUsize::new(0),
+            texture: Mutex::new(None),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The area the HUD needs to redraw, or to restore after it was hidden.
+    pub(super) fn damage(&self) -> Option<DamageRect> {
+        (self.is_enabled() || self.drawn.load(Ordering::Relaxed))
+            .then(|| DamageRect::new(0, 0, HUD_WIDTH, HUD_HEIGHT))
+    }
+
+    pub(super) fn vsync(&self, frame_start: u64) {
+        self.last_vsync.store(frame_start, Ordering::Relaxed);
+    }
+
+    /// Called for every task on the render thread. The first one after a
+    /// vsync marks the end of the build phase.
+    pub(super) fn render_task_started(&self) {
+        let now = FlutterApplication::current_time();
+        let last_vsync = self.last_vsync.load(Ordering::Relaxed);
+        if self.raster_start.load(Ordering::Relaxed) < last_vsync && now >= last_vsync {
+            self.raster_start.store(now, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn backing_store_created(&self, bytes: usize) {
+        self.backing_stores.fetch_add(1, Ordering::Relaxed);
+        self.backing_store_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(super) fn backing_store_destroyed(&self, bytes: usize) {
+        self.backing_stores.fetch_sub(1, Ordering::Relaxed);
+        self.backing_store_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub(super) fn platform_task_posted(&self) {
+        self.platform_queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn platform_task_finished(&self) {
+        self.platform_queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn pointer_events_sent(&self, count: usize) {
+        self.pointer_events.fetch_add(count, Ordering::Relaxed);
+        self.pointer_batches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn presented(&self, present_start: u64) {
+        self.last_present_duration.store(
+            FlutterApplication::current_time().saturating_sub(present_start),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Records the commands to copy the HUD into the top left corner of the
+    /// frame's target.
+    pub(super) fn draw(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        frame: PerfHudFrame,
+    ) {
+        let PerfHudFrame {
+            target,
+            target_size,
+            layers,
+            present_start,
+        } = frame;
+        let enabled = self.is_enabled();
+        self.drawn.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            return;
+        }
+        let last_vsync = self.last_vsync.load(Ordering::Relaxed);
+        let raster_start = self.raster_start.load(Ordering::Relaxed).max(last_vsync);
+        let millis = |nanos: u64| nanos as f64 / 1_000_000.0;
+        let lines = [
+            format!("BUILD {:.2}MS", millis(raster_start - last_vsync)),
+            format!(
+                "RASTER {:.2}MS",
+                millis(present_start.saturating_sub(raster_start))
+            ),
+            format!(
+                "PRESENT {:.2}MS",
+                millis(self.last_present_duration.load(Ordering::Relaxed))
+            ),
+            format!("LAYERS {layers}"),
+            format!("STORES {}", self.backing_stores.load(Ordering::Relaxed)),
+            format!(
+                "MEMORY {:.1}MB",
+                self.backing_store_bytes.load(Ordering::Relaxed) as f64 / (1024.0 * 1024.0)
+            ),
+            format!(
+                "QUEUE {}",
+                self.platform_queue_depth.load(Ordering::Relaxed)
+            ),
+            format!(
+                "INPUT {} IN {}",
+                self.pointer_events.swap(0, Ordering::Relaxed),
+                self.pointer_batches.swap(0, Ordering::Relaxed)
+            ),
+        ];
+
+        let mut pixels = BACKGROUND.repeat((HUD_WIDTH * HUD_HEIGHT) as _);
+        for (line_idx, line) in lines.iter().enumerate() {
+            let top = PADDING + line_idx as u32 * LINE_HEIGHT;
+            for (char_idx, c) in line.chars().enumerate() {
+                let left = PADDING + char_idx as u32 * GLYPH_ADVANCE;
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    for column in 0..3 {
+                        if bits & (0b100 >> column) == 0 {
+                            continue;
+                        }
+                        for dy in 0..FONT_SCALE {
+                            for dx in 0..FONT_SCALE {
+                                let x = left + column * FONT_SCALE + dx;
+                                let y = top + row as u32 * FONT_SCALE + dy;
+                                if x < HUD_WIDTH && y < HUD_HEIGHT {
+                                    let offset = ((y * HUD_WIDTH + x) * 4) as usize;
+                                    pixels[offset..offset + 4].copy_from_slice(&FOREGROUND);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let size = Extent3d {
+            width: HUD_WIDTH,
+            height: HUD_HEIGHT,
+            depth_or_array_layers: 1,
+        };
+        let mut texture = self.texture.lock().unwrap();
+        let texture = texture.get_or_insert_with(|| {
+            device.create_texture(&TextureDescriptor {
+                label: Some("Performance HUD"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Bgra8Unorm,
+                usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+            })
+        });
+        queue.write_texture(
+            ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(HUD_WIDTH * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+        encoder.copy_texture_to_texture(
+            ImageCopyTextureBase {
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyTextureBase {
+                texture: target,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            Extent3d {
+                width: HUD_WIDTH.min(target_size.0),
+                height: HUD_HEIGHT.min(target_size.1),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
@@ -1,55 +1,872 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ffi::CString, mem::size_of, ptr::null};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::KeyEvent,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(super) struct FlutterSize {
-    width: f64,
-    height: f64,
+use crate::flutter_bindings::{
+    FlutterEngine, FlutterEngineSendPlatformMessage, FlutterPlatformMessage,
+};
+
+use super::{FlutterApplication, PointerState, FLUTTER_PLATFORM_VIEWS_CHANNEL};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct FlutterSize {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct FlutterOffset {
+    pub top: f64,
+    pub left: f64,
+}
+
+/// The text direction of a platform view, as encoded by the framework
+/// (0 = left-to-right, 1 = right-to-left).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(from = "i32", into = "i32")]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl From<i32> for TextDirection {
+    fn from(value: i32) -> Self {
+        if value == 1 {
+            Self::Rtl
+        } else {
+            Self::Ltr
+        }
+    }
+}
+
+impl From<TextDirection> for i32 {
+    fn from(direction: TextDirection) -> Self {
+        match direction {
+            TextDirection::Ltr => 0,
+            TextDirection::Rtl => 1,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "method", content = "args", rename_all = "camelCase")]
 pub(super) enum PlatformViewMessage {
-    Create(PlatformView),
-    // ClearFocus {
-    //     id: i64,
-    // },
-    // PointerEvent {
-    //     id: i64,
-    //     event: Value,
-    // },
-    Dispose(i32),
+    Create(PlatformViewCreateArgs),
+    Dispose(PlatformViewDisposeArgs),
+    Resize {
+        id: i32,
+        width: f64,
+        height: f64,
+    },
+    Offset {
+        id: i32,
+        top: f64,
+        left: f64,
+    },
+    Touch(PlatformViewTouch),
+    SetDirection {
+        id: i32,
+        direction: TextDirection,
+    },
+    ClearFocus(i32),
+    SynchronizeToNativeViewHierarchy(bool),
+    /// The gesture arena has been won by the platform view, so all pointer
+    /// events of the current gesture should be delivered to it.
+    AcceptGesture {
+        id: i32,
+    },
+    /// The framework has claimed the current gesture.
+    RejectGesture {
+        id: i32,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub(super) struct PlatformView {
+pub(super) struct PlatformViewCreateArgs {
     id: i32,
     view_type: String,
-    size: Option<FlutterSize>,
+    #[serde(default)]
+    width: Option<f64>,
+    #[serde(default)]
+    height: Option<f64>,
+    #[serde(default)]
+    direction: Option<TextDirection>,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+/// `dispose` is either sent with the bare view id or with a map that
+/// additionally specifies whether the view uses hybrid composition.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub(super) enum PlatformViewDisposeArgs {
+    Id(i32),
+    Map {
+        id: i32,
+        #[serde(default)]
+        hybrid: bool,
+    },
+}
+
+impl PlatformViewDisposeArgs {
+    fn id(&self) -> i32 {
+        match self {
+            Self::Id(id) | Self::Map { id, .. } => *id,
+        }
+    }
+}
+
+/// A motion event as sent by `AndroidViewController.sendMotionEvent`. The
+/// framework encodes it as a positional list, which serde maps onto the
+/// fields in declaration order.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(super) struct PlatformViewTouch {
+    id: i32,
+    down_time: i64,
+    event_time: i64,
+    action: i32,
+    pointer_count: i32,
+    /// `[pointer id, tool type]` for every pointer.
+    pointer_properties: Vec<(i32, i32)>,
+    /// `[orientation, pressure, size, toolMajor, toolMinor, touchMajor,
+    /// touchMinor, x, y]` for every pointer.
+    pointer_coords: Vec<[f64; 9]>,
+    meta_state: i32,
+    button_state: i32,
+    x_precision: f64,
+    y_precision: f64,
+    device_id: i32,
+    edge_flags: i32,
+    source: i32,
+    flags: i32,
+    #[serde(default)]
+    motion_event_id: Option<i64>,
+}
+
+/// The action of a [`PlatformViewPointerEvent`], matching the values of
+/// Android's `MotionEvent` that the framework uses on this channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlatformViewPointerAction {
+    Down,
+    Up,
+    Move,
+    Cancel,
+    PointerDown,
+    PointerUp,
+    Hover,
+}
+
+impl PlatformViewPointerAction {
+    fn from_motion_event_action(action: i32) -> Option<Self> {
+        // The index of the affected pointer is encoded in the second byte.
+        Some(match action & 0xff {
+            0 => Self::Down,
+            1 => Self::Up,
+            2 => Self::Move,
+            3 => Self::Cancel,
+            5 => Self::PointerDown,
+            6 => Self::PointerUp,
+            7 => Self::Hover,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlatformViewPointer {
+    pub id: i32,
+    /// Position in logical pixels relative to the top left corner of the view.
+    pub x: f64,
+    pub y: f64,
+    pub pressure: f64,
+    /// Angle between a stylus and the surface normal in radians, 0 for
+    /// everything else.
+    pub tilt: f64,
+}
+
+/// A pointer event forwarded to a platform view. All coordinates are already
+/// converted into the view-local coordinate space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformViewPointerEvent {
+    pub action: PlatformViewPointerAction,
+    /// Index into `pointers` of the pointer that caused this event.
+    pub action_index: usize,
+    pub pointers: Vec<PlatformViewPointer>,
+    pub buttons: i64,
+    /// Timestamp in milliseconds.
+    pub timestamp: i64,
+}
+
+/// Implemented by the embedder for every kind of native view that can be
+/// embedded into the Flutter widget tree.
+pub trait PlatformView {
+    fn resize(&mut self, _size: FlutterSize) {}
+    fn set_offset(&mut self, _offset: FlutterOffset) {}
+    fn set_direction(&mut self, _direction: TextDirection) {}
+    fn pointer_event(&mut self, _event: PlatformViewPointerEvent) {}
+    fn focus_changed(&mut self, _focused: bool) {}
+    /// Only called while the view has keyboard focus. Returns whether the
+    /// view handled the key, otherwise it goes on to Flutter.
+    fn key_event(&mut self, _event: &KeyEvent) -> bool {
+        false
+    }
+}
+
+/// Creates a new platform view from its id and the creation parameters
+/// supplied by the framework.
+pub type PlatformViewFactory = Box<dyn Fn(i32, Option<Value>) -> Box<dyn PlatformView>>;
+
+/// A clip applied to a platform view layer, in physical window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum PlatformViewClip {
+    Rect {
+        left: f64,
+        top: f64,
+        right: f64,
+        bottom: f64,
+    },
+    RoundedRect {
+        left: f64,
+        top: f64,
+        right: f64,
+        bottom: f64,
+        /// Radii of the upper left, upper right, lower right and lower left
+        /// corners.
+        radii: [PhysicalSize<f64>; 4],
+    },
+}
+
+impl PlatformViewClip {
+    fn contains(&self, position: PhysicalPosition<f64>) -> bool {
+        match *self {
+            Self::Rect {
+                left,
+                top,
+                right,
+                bottom,
+            } => {
+                position.x >= left && position.x < right && position.y >= top && position.y < bottom
+            }
+            Self::RoundedRect {
+                left,
+                top,
+                right,
+                bottom,
+                radii,
+            } => {
+                if position.x < left
+                    || position.x >= right
+                    || position.y < top
+                    || position.y >= bottom
+                {
+                    return false;
+                }
+                let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+                corners.iter().zip(radii.iter()).all(|(&(x, y), radius)| {
+                    if radius.width <= 0.0 || radius.height <= 0.0 {
+                        return true;
+                    }
+                    let center_x = if x == left {
+                        x + radius.width
+                    } else {
+                        x - radius.width
+                    };
+                    let center_y = if y == top {
+                        y + radius.height
+                    } else {
+                        y - radius.height
+                    };
+                    let dx = position.x - center_x;
+                    let dy = position.y - center_y;
+                    // Only the quadrant facing the corner is rounded off.
+                    let outside_x = if x == left { dx < 0.0 } else { dx > 0.0 };
+                    let outside_y = if y == top { dy < 0.0 } else { dy > 0.0 };
+                    !(outside_x && outside_y)
+                        || (dx / radius.width).powi(2) + (dy / radius.height).powi(2) <= 1.0
+                })
+            }
+        }
+    }
+}
+
+/// The placement of a platform view in the last presented frame.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PlatformViewLayer {
+    pub(super) id: i32,
+    pub(super) offset: PhysicalPosition<f64>,
+    pub(super) size: PhysicalSize<f64>,
+    pub(super) clips: Vec<PlatformViewClip>,
+}
+
+impl PlatformViewLayer {
+    fn contains(&self, position: PhysicalPosition<f64>) -> bool {
+        position.x >= self.offset.x
+            && position.x < self.offset.x + self.size.width
+            && position.y >= self.offset.y
+            && position.y < self.offset.y + self.size.height
+            && self.clips.iter().all(|clip| clip.contains(position))
+    }
+}
+
+enum GestureState {
+    /// The gesture arena hasn't decided yet, so events are held back.
+    Pending(Vec<PlatformViewPointerEvent>),
+    Accepted,
+    Rejected,
+}
+
+struct Gesture {
+    view: i32,
+    pointer: i32,
+    state: GestureState,
+    ended: bool,
+}
+
+struct PlatformViewState {
+    size: FlutterSize,
+    offset: FlutterOffset,
+    view: Box<dyn PlatformView>,
 }
 
 #[derive(Default)]
 pub(super) struct PlatformViewsHandler {
-    views: HashMap<i32, PlatformView>,
+    factories: HashMap<String, PlatformViewFactory>,
+    views: HashMap<i32, PlatformViewState>,
+    focused_view: Option<i32>,
+    gesture: Option<Gesture>,
+}
+
+fn success(value: Value) -> Option<Vec<u8>> {
+    Some(serde_json::to_vec(&Value::Array(vec![value])).unwrap())
+}
+
+fn error(code: &str, message: String) -> Option<Vec<u8>> {
+    Some(
+        serde_json::to_vec(&Value::Array(vec![
+            Value::String(code.to_owned()),
+            Value::String(message),
+            Value::Null,
+        ]))
+        .unwrap(),
+    )
 }
 
 impl PlatformViewsHandler {
+    pub(super) fn register_view_type(&mut self, view_type: String, factory: PlatformViewFactory) {
+        self.factories.insert(view_type, factory);
+    }
+
     pub(super) fn handle_platform_views_message(
         &mut self,
+        engine: FlutterEngine,
         message: PlatformViewMessage,
     ) -> Option<Vec<u8>> {
         match message {
-            PlatformViewMessage::Create(view) => {
-                self.views.insert(view.id, view);
-                Some(serde_json::to_vec(&Value::Array(vec![Value::Bool(true)])).unwrap())
+            PlatformViewMessage::Create(args) => {
+                if self.views.contains_key(&args.id) {
+                    return error(
+                        "error",
+                        format!(
+                            "Trying to create an already created platform view, view id: {}",
+                            args.id
+                        ),
+                    );
+                }
+                let factory = if let Some(factory) = self.factories.get(&args.view_type) {
+                    factory
+                } else {
+                    return error(
+                        "error",
+                        format!(
+                            "Trying to create a platform view of unregistered type: {}",
+                            args.view_type
+                        ),
+                    );
+                };
+                let size = FlutterSize {
+                    width: args.width.unwrap_or_default(),
+                    height: args.height.unwrap_or_default(),
+                };
+                let direction = args.direction.unwrap_or_default();
+                let mut view = factory(args.id, args.params);
+                view.resize(size);
+                view.set_direction(direction);
+                self.views.insert(
+                    args.id,
+                    PlatformViewState {
+                        size,
+                        offset: Default::default(),
+                        view,
+                    },
+                );
+                success(Value::Null)
+            }
+            PlatformViewMessage::Dispose(args) => {
+                let id = args.id();
+                if self.views.remove(&id).is_none() {
+                    return error(
+                        "error",
+                        format!("Trying to dispose a platform view with unknown id: {id}"),
+                    );
+                }
+                if self.focused_view == Some(id) {
+                    self.focused_view = None;
+                }
+                if self.gesture.as_ref().map(|gesture| gesture.view) == Some(id) {
+                    self.gesture = None;
+                }
+                success(Value::Null)
+            }
+            PlatformViewMessage::Resize { id, width, height } => {
+                if let Some(state) = self.views.get_mut(&id) {
+                    state.size = FlutterSize { width, height };
+                    state.view.resize(state.size);
+                    success(serde_json::to_value(state.size).unwrap())
+                } else {
+                    error(
+                        "error",
+                        format!("Trying to resize a platform view with unknown id: {id}"),
+                    )
+                }
+            }
+            PlatformViewMessage::Offset { id, top, left } => {
+                if let Some(state) = self.views.get_mut(&id) {
+                    state.offset = FlutterOffset { top, left };
+                    state.view.set_offset(state.offset);
+                    success(Value::Null)
+                } else {
+                    error(
+                        "error",
+                        format!(
+                            "Trying to set the offset of a platform view with unknown id: {id}"
+                        ),
+                    )
+                }
+            }
+            PlatformViewMessage::Touch(touch) => {
+                let id = touch.id;
+                if let Some(state) = self.views.get_mut(&id) {
+                    if let Some(event) = Self::convert_touch(touch) {
+                        state.view.pointer_event(event);
+                    }
+                    success(Value::Null)
+                } else {
+                    error(
+                        "error",
+                        format!("Sending touch to an unknown platform view with id: {id}"),
+                    )
+                }
+            }
+            PlatformViewMessage::SetDirection { id, direction } => {
+                if let Some(state) = self.views.get_mut(&id) {
+                    state.view.set_direction(direction);
+                    success(Value::Null)
+                } else {
+                    error(
+                        "error",
+                        format!(
+                            "Trying to set the direction of a platform view with unknown id: {id}"
+                        ),
+                    )
+                }
+            }
+            PlatformViewMessage::ClearFocus(id) => {
+                if let Some(state) = self.views.get_mut(&id) {
+                    if self.focused_view == Some(id) {
+                        self.focused_view = None;
+                    }
+                    state.view.focus_changed(false);
+                    success(Value::Null)
+                } else {
+                    error(
+                        "error",
+                        format!(
+                            "Trying to clear the focus of a platform view with unknown id: {id}"
+                        ),
+                    )
+                }
+            }
+            PlatformViewMessage::SynchronizeToNativeViewHierarchy(_) => {
+                // All views are composited by us in the same pass, so they are
+                // always in sync with the Flutter layers.
+                success(Value::Null)
+            }
+            PlatformViewMessage::AcceptGesture { id } => {
+                match &mut self.gesture {
+                    Some(gesture) if gesture.view == id => {
+                        let state = std::mem::replace(&mut gesture.state, GestureState::Accepted);
+                        let ended = gesture.ended;
+                        if let (GestureState::Pending(events), Some(view)) =
+                            (state, self.views.get_mut(&id))
+                        {
+                            for event in events {
+                                view.view.pointer_event(event);
+                            }
+                        }
+                        if ended {
+                            self.gesture = None;
+                        }
+                        self.focus_view(engine, Some(id));
+                    }
+                    _ => {
+                        log::warn!("acceptGesture for platform view {id} without active gesture");
+                    }
+                }
+                success(Value::Null)
+            }
+            PlatformViewMessage::RejectGesture { id } => {
+                match &mut self.gesture {
+                    Some(gesture) if gesture.view == id => {
+                        if gesture.ended {
+                            self.gesture = None;
+                        } else {
+                            gesture.state = GestureState::Rejected;
+                        }
+                    }
+                    _ => {
+                        log::warn!("rejectGesture for platform view {id} without active gesture");
+                    }
+                }
+                success(Value::Null)
+            }
+        }
+    }
+
+    /// Finds the topmost platform view at the given position of the last
+    /// presented frame.
+    pub(super) fn hit_test(
+        &self,
+        layers: &[PlatformViewLayer],
+        position: PhysicalPosition<f64>,
+    ) -> Option<i32> {
+        layers
+            .iter()
+            .rev()
+            .find(|layer| self.views.contains_key(&layer.id) && layer.contains(position))
+            .map(|layer| layer.id)
+    }
+
+    /// Converts a position in physical window coordinates into logical
+    /// coordinates relative to the given view.
+    fn to_local(
+        &self,
+        layers: &[PlatformViewLayer],
+        id: i32,
+        position: PhysicalPosition<f64>,
+    ) -> Option<PhysicalPosition<f64>> {
+        let layer = layers.iter().find(|layer| layer.id == id)?;
+        let size = self.views.get(&id)?.size;
+        let scale_x = if layer.size.width > 0.0 {
+            size.width / layer.size.width
+        } else {
+            1.0
+        };
+        let scale_y = if layer.size.height > 0.0 {
+            size.height / layer.size.height
+        } else {
+            1.0
+        };
+        Some(PhysicalPosition::new(
+            (position.x - layer.offset.x) * scale_x,
+            (position.y - layer.offset.y) * scale_y,
+        ))
+    }
+
+    /// Routes a pointer event to the platform views. Hover events go straight
+    /// to the view under the pointer, everything belonging to a gesture is
+    /// held back until the framework accepts or rejects the gesture.
+    pub(super) fn pointer_event(
+        &mut self,
+        engine: FlutterEngine,
+        layers: &[PlatformViewLayer],
+        state: &PointerState,
+        action: PlatformViewPointerAction,
+    ) {
+        let position = state.position;
+        let pointer = state.virtual_id;
+        let buttons = state.held_buttons as i64;
+        let gesture_view = self
+            .gesture
+            .as_ref()
+            .filter(|gesture| gesture.pointer == pointer)
+            .map(|gesture| gesture.view);
+        let target = if gesture_view.is_some() {
+            gesture_view
+        } else if action == PlatformViewPointerAction::Down {
+            let hit = self.hit_test(layers, position);
+            if hit.is_none() {
+                // Clicking into Flutter content moves the focus away from
+                // the platform view.
+                self.focus_view(engine, None);
+            }
+            hit
+        } else if action == PlatformViewPointerAction::Hover {
+            self.hit_test(layers, position)
+        } else {
+            None
+        };
+        let id = if let Some(id) = target { id } else { return };
+        let local = if let Some(local) = self.to_local(layers, id, position) {
+            local
+        } else {
+            return;
+        };
+        let event = PlatformViewPointerEvent {
+            action,
+            action_index: 0,
+            pointers: vec![PlatformViewPointer {
+                id: pointer,
+                x: local.x,
+                y: local.y,
+                pressure: state
+                    .pressure
+                    .unwrap_or(if buttons != 0 { 1.0 } else { 0.0 }),
+                tilt: state.tilt,
+            }],
+            buttons,
+            timestamp: (FlutterApplication::current_time() / 1_000_000) as _,
+        };
+        let ends_gesture = matches!(
+            action,
+            PlatformViewPointerAction::Up | PlatformViewPointerAction::Cancel
+        );
+
+        if action == PlatformViewPointerAction::Down && self.gesture.is_none() {
+            self.gesture = Some(Gesture {
+                view: id,
+                pointer,
+                state: GestureState::Pending(vec![event]),
+                ended: false,
+            });
+            return;
+        }
+        match &mut self.gesture {
+            Some(gesture) if gesture.pointer == pointer => {
+                match &mut gesture.state {
+                    GestureState::Pending(events) => events.push(event),
+                    GestureState::Accepted => {
+                        if let Some(view) = self.views.get_mut(&id) {
+                            view.view.pointer_event(event);
+                        }
+                    }
+                    GestureState::Rejected => {}
+                }
+                if ends_gesture {
+                    if matches!(gesture.state, GestureState::Pending(_)) {
+                        // Keep the events around until the arena is resolved.
+                        gesture.ended = true;
+                    } else {
+                        self.gesture = None;
+                    }
+                }
             }
-            PlatformViewMessage::Dispose(id) => {
-                self.views.remove(&id);
-                Some(serde_json::to_vec(&Value::Array(vec![Value::Bool(true)])).unwrap())
+            Some(Gesture {
+                state: GestureState::Pending(_),
+                ..
+            }) if action != PlatformViewPointerAction::Hover => {
+                // Another pointer going down before the framework decided
+                // about the first one. Delivering it would let the view see
+                // the second contact before the first, so it's dropped.
             }
+            _ => {
+                if let Some(view) = self.views.get_mut(&id) {
+                    view.view.pointer_event(event);
+                }
+            }
+        }
+    }
+
+    /// Forwards the key event to the focused platform view. Returns whether
+    /// it handled the event, in which case it must not go to Flutter.
+    pub(super) fn key_event(&mut self, event: &KeyEvent) -> bool {
+        self.focused_view
+            .and_then(|focused_view| self.views.get_mut(&focused_view))
+            .map_or(false, |view| view.view.key_event(event))
+    }
+
+    fn focus_view(&mut self, engine: FlutterEngine, id: Option<i32>) {
+        if self.focused_view == id {
+            return;
+        }
+        if let Some(view) = self
+            .focused_view
+            .and_then(|focused_view| self.views.get_mut(&focused_view))
+        {
+            view.view.focus_changed(false);
+        }
+        self.focused_view = id;
+        if let Some(id) = id {
+            if let Some(view) = self.views.get_mut(&id) {
+                view.view.focus_changed(true);
+            }
+            // Let the framework know, so it can unfocus its own text fields.
+            let channel = CString::new(FLUTTER_PLATFORM_VIEWS_CHANNEL).unwrap();
+            let message = serde_json::to_vec(&serde_json::json!({
+                "method": "viewFocused",
+                "args": id,
+            }))
+            .unwrap();
+            FlutterApplication::unwrap_result(unsafe {
+                FlutterEngineSendPlatformMessage(
+                    engine,
+                    &FlutterPlatformMessage {
+                        struct_size: size_of::<FlutterPlatformMessage>() as _,
+                        channel: channel.as_ptr(),
+                        message: message.as_ptr(),
+                        message_size: message.len() as _,
+                        response_handle: null(),
+                    },
+                )
+            });
+        }
+    }
+
+    /// Touch coordinates sent by the framework are in logical pixels and
+    /// relative to the top left corner of the platform view already.
+    fn convert_touch(touch: PlatformViewTouch) -> Option<PlatformViewPointerEvent> {
+        let action = if let Some(action) =
+            PlatformViewPointerAction::from_motion_event_action(touch.action)
+        {
+            action
+        } else {
+            log::warn!("Unknown motion event action {}", touch.action);
+            return None;
+        };
+        let pointers = touch
+            .pointer_properties
+            .iter()
+            .zip(touch.pointer_coords.iter())
+            .map(|(&(id, _tool_type), coords)| PlatformViewPointer {
+                id,
+                x: coords[7],
+                y: coords[8],
+                pressure: coords[1],
+                // Motion events from the framework don't carry the tilt.
+                tilt: 0.0,
+            })
+            .collect();
+        Some(PlatformViewPointerEvent {
+            action,
+            action_index: ((touch.action >> 8) & 0xff) as _,
+            pointers,
+            buttons: touch.button_state as _,
+            timestamp: touch.event_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, ptr::null_mut, rc::Rc};
+
+    use super::*;
+    use crate::flutter_bindings::FlutterPointerDeviceKind_kFlutterPointerDeviceKindTouch;
+
+    struct RecordingView(Rc<RefCell<Vec<PlatformViewPointerEvent>>>);
+
+    impl PlatformView for RecordingView {
+        fn pointer_event(&mut self, event: PlatformViewPointerEvent) {
+            self.0.borrow_mut().push(event);
+        }
+    }
+
+    fn finger(virtual_id: i32, x: f64, y: f64) -> PointerState {
+        PointerState {
+            virtual_id,
+            position: PhysicalPosition::new(x, y),
+            held_buttons: 1,
+            device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindTouch,
+            pressure: None,
+            tilt: 0.0,
+            pan_zoom: None,
         }
     }
+
+    #[test]
+    fn other_pointers_wait_for_the_pending_gesture() {
+        let received = Rc::new(RefCell::new(vec![]));
+        let mut handler = PlatformViewsHandler::default();
+        let size = FlutterSize {
+            width: 100.0,
+            height: 100.0,
+        };
+        handler.views.insert(
+            1,
+            PlatformViewState {
+                size,
+                offset: Default::default(),
+                view: Box::new(RecordingView(received.clone())),
+            },
+        );
+        let layers = [PlatformViewLayer {
+            id: 1,
+            offset: PhysicalPosition::new(0.0, 0.0),
+            size: PhysicalSize::new(100.0, 100.0),
+            clips: vec![],
+        }];
+        // Both fingers hit the view, so the framework isn't called.
+        let engine = null_mut();
+        let down = PlatformViewPointerAction::Down;
+        handler.pointer_event(engine, &layers, &finger(0, 10.0, 10.0), down);
+        handler.pointer_event(engine, &layers, &finger(1, 20.0, 20.0), down);
+
+        assert!(received.borrow().is_empty());
+        let gesture = handler.gesture.as_ref().unwrap();
+        assert_eq!(gesture.pointer, 0);
+        match &gesture.state {
+            GestureState::Pending(events) => {
+                assert_eq!(events.len(), 1);
+                assert_eq!(events[0].pointers[0].id, 0);
+            }
+            _ => panic!("the gesture was decided without the framework"),
+        }
+    }
+
+    #[test]
+    fn touches_are_already_relative_to_the_view() {
+        let coords = |x, y| [0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, x, y];
+        let touch = PlatformViewTouch {
+            id: 1,
+            down_time: 0,
+            event_time: 42,
+            // The second pointer going down.
+            action: 5 | (1 << 8),
+            pointer_count: 2,
+            pointer_properties: vec![(3, 1), (4, 1)],
+            pointer_coords: vec![coords(10.0, 20.0), coords(30.0, 40.0)],
+            meta_state: 0,
+            button_state: 0,
+            x_precision: 1.0,
+            y_precision: 1.0,
+            device_id: 0,
+            edge_flags: 0,
+            source: 0,
+            flags: 0,
+            motion_event_id: None,
+        };
+        let pointer = |id, x, y| PlatformViewPointer {
+            id,
+            x,
+            y,
+            pressure: 0.5,
+            tilt: 0.0,
+        };
+        assert_eq!(
+            PlatformViewsHandler::convert_touch(touch),
+            Some(PlatformViewPointerEvent {
+                action: PlatformViewPointerAction::PointerDown,
+                action_index: 1,
+                pointers: vec![pointer(3, 10.0, 20.0), pointer(4, 30.0, 40.0)],
+                buttons: 0,
+                timestamp: 42,
+            })
+        );
+    }
 }
@@ -0,0 +1,304 @@
+use std::{
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+use wgpu::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, Maintain, MapMode, Origin3d, PresentMode, Queue, Surface,
+    SurfaceConfiguration, SurfaceError, SurfaceTexture, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+/// Controls the trade-off between latency and power usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationConfig {
+    /// With `Fifo`, frames are aligned to the display's vsync. `Mailbox` and
+    /// `Immediate` start a new frame as soon as the engine asks for one.
+    pub present_mode: PresentMode,
+    /// How many frames may be queued on the GPU before the engine has to wait
+    /// for the oldest one to finish.
+    pub frames_in_flight: u32,
+}
+
+impl Default for PresentationConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::Fifo,
+            frames_in_flight: 2,
+        }
+    }
+}
+
+impl PresentationConfig {
+    /// Falls back to `Fifo` if the requested present mode isn't in the list
+    /// of modes the surface supports. `Fifo` is always supported.
+    pub fn supported(self, supported_modes: &[PresentMode]) -> Self {
+        if supported_modes.contains(&self.present_mode) {
+            self
+        } else {
+            log::warn!(
+                "Present mode {:?} is not supported by the adapter, falling back to Fifo",
+                self.present_mode
+            );
+            Self {
+                present_mode: PresentMode::Fifo,
+                ..self
+            }
+        }
+    }
+
+    pub(super) fn is_vsync_aligned(&self) -> bool {
+        self.present_mode == PresentMode::Fifo
+    }
+}
+
+/// Where the compositor puts the final frame.
+pub enum RenderTarget {
+    /// Present directly to the swapchain of a window.
+    Surface(SurfaceTarget),
+    /// Composite into a texture that is owned by the host renderer, which
+    /// takes care of displaying it (for example as a HUD).
+    Texture(TextureTarget),
+}
+
+/// A window surface together with its configuration, so it can be
+/// reconfigured when the swapchain becomes outdated or is lost.
+pub struct SurfaceTarget {
+    surface: Surface,
+    config: Mutex<SurfaceConfiguration>,
+    /// Set by [`Self::resize`], applied before acquiring the next frame so
+    /// the surface isn't reconfigured while a frame of it is in use.
+    pending_size: Mutex<Option<(u32, u32)>>,
+}
+
+impl SurfaceTarget {
+    pub fn new(surface: Surface, device: &Device, config: SurfaceConfiguration) -> Self {
+        surface.configure(device, &config);
+        Self {
+            surface,
+            config: Mutex::new(config),
+            pending_size: Mutex::new(None),
+        }
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    /// Resizes the swapchain to `width`×`height` physical pixels, starting
+    /// with the next frame. Empty sizes (for example of a minimized window)
+    /// are ignored, since a surface can't be configured with them.
+    pub fn resize(&self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            *self.pending_size.lock().unwrap() = Some((width, height));
+        }
+    }
+
+    /// The size of the swapchain images.
+    pub(super) fn size(&self) -> (u32, u32) {
+        let config = self.config.lock().unwrap();
+        (config.width, config.height)
+    }
+
+    fn reconfigure(&self, device: &Device) {
+        self.surface.configure(device, &self.config.lock().unwrap());
+    }
+
+    fn acquire(&self, device: &Device) -> Option<SurfaceTexture> {
+        if let Some((width, height)) = self.pending_size.lock().unwrap().take() {
+            let mut config = self.config.lock().unwrap();
+            if (config.width, config.height) != (width, height) {
+                config.width = width;
+                config.height = height;
+                self.surface.configure(device, &config);
+            }
+        }
+        match self.surface.get_current_texture() {
+            Ok(frame) => Some(frame),
+            Err(err @ (SurfaceError::Outdated | SurfaceError::Lost)) => {
+                log::warn!("{err}, reconfiguring the surface");
+                self.reconfigure(device);
+                self.surface
+                    .get_current_texture()
+                    .map_err(|err| log::error!("Failed to acquire next swap chain texture: {err}"))
+                    .ok()
+            }
+            Err(err) => {
+                log::error!("Failed to acquire next swap chain texture: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// A texture shared between the compositor and the host renderer.
+///
+/// The texture needs at least `TextureUsages::COPY_DST` and has to use the
+/// `Bgra8Unorm` format, since layers are copied into it directly.
+pub struct TextureTarget {
+    /// The texture and its size, which wgpu doesn't keep track of.
+    texture: Mutex<(Arc<Texture>, (u32, u32))>,
+    frame_ready: Box<dyn Fn(&Arc<Texture>) + Send + Sync + 'static>,
+}
+
+impl TextureTarget {
+    /// `frame_ready` is called on the render thread every time a new frame
+    /// has been submitted to the queue.
+    pub fn new(
+        texture: Arc<Texture>,
+        width: u32,
+        height: u32,
+        frame_ready: impl Fn(&Arc<Texture>) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            texture: Mutex::new((texture, (width, height))),
+            frame_ready: Box::new(frame_ready),
+        }
+    }
+
+    /// Creates a texture suitable for [`TextureTarget`] that can also be
+    /// sampled by the host renderer.
+    pub fn create_texture(device: &Device, width: u32, height: u32) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some("Flutter Render Target"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8Unorm,
+            usage: TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT,
+        })
+    }
+
+    /// Replaces the texture, which is `width`×`height` large, for example
+    /// after the host resized the view. Takes effect with the next frame.
+    pub fn set_texture(&self, texture: Arc<Texture>, width: u32, height: u32) {
+        *self.texture.lock().unwrap() = (texture, (width, height));
+    }
+
+    pub fn texture(&self) -> Arc<Texture> {
+        self.texture.lock().unwrap().0.clone()
+    }
+
+    /// Copies the current contents of the texture back to the CPU. Blocks
+    /// until the GPU is done. Returns tightly packed BGRA rows.
+    pub fn read_pixels(&self, device: &Device, queue: &Queue, width: u32, height: u32) -> Vec<u8> {
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = (unpadded_bytes_per_row + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+            / COPY_BYTES_PER_ROW_ALIGNMENT
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Flutter Render Target Readback"),
+            size: (padded_bytes_per_row * height) as _,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let texture = self.texture();
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(MapMode::Read, |result| {
+            result.expect("Failed to map readback buffer");
+        });
+        device.poll(Maintain::Wait);
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as _)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as _])
+            .copied()
+            .collect();
+        buffer.unmap();
+        pixels
+    }
+}
+
+/// The texture a single frame is composited into.
+pub(super) enum FrameTexture {
+    Surface(SurfaceTexture, (u32, u32)),
+    Texture(Arc<Texture>, (u32, u32)),
+}
+
+impl FrameTexture {
+    pub(super) fn texture(&self) -> &Texture {
+        match self {
+            Self::Surface(frame, _) => &frame.texture,
+            Self::Texture(texture, _) => texture,
+        }
+    }
+
+    /// Might differ from the size of the layers, for example while the
+    /// window is being resized.
+    pub(super) fn size(&self) -> (u32, u32) {
+        match self {
+            Self::Surface(_, size) | Self::Texture(_, size) => *size,
+        }
+    }
+}
+
+impl RenderTarget {
+    /// Returns `None` if no frame could be acquired, for example because the
+    /// device was lost. The frame should be skipped then.
+    pub(super) fn acquire_frame(&self, device: &Device) -> Option<FrameTexture> {
+        match self {
+            Self::Surface(target) => target
+                .acquire(device)
+                .map(|frame| FrameTexture::Surface(frame, target.size())),
+            Self::Texture(target) => {
+                let (texture, size) = target.texture.lock().unwrap().clone();
+                Some(FrameTexture::Texture(texture, size))
+            }
+        }
+    }
+
+    /// Must be called after the command buffers writing to the frame have
+    /// been submitted.
+    pub(super) fn present(&self, frame: FrameTexture) {
+        match (self, frame) {
+            (_, FrameTexture::Surface(frame, _)) => frame.present(),
+            (Self::Texture(target), FrameTexture::Texture(texture, _)) => {
+                (target.frame_ready)(&texture)
+            }
+            (Self::Surface(_), FrameTexture::Texture(..)) => unreachable!(),
+        }
+    }
+
+    pub fn surface(&self) -> Option<&Surface> {
+        if let Self::Surface(target) = self {
+            Some(target.surface())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::message_codec;
+
+/// Messages on the `flutter/restoration` channel, encoded with the standard
+/// method codec.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) enum RestorationMessage {
+    /// The framework asks for the data to restore from.
+    Get(()),
+    /// The framework hands over new data to persist.
+    Put(RestorationData),
+}
+
+pub(super) struct RestorationData(pub Vec<u8>);
+
+impl fmt::Debug for RestorationData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RestorationData({} bytes)", self.0.len())
+    }
+}
+
+impl<'de> Deserialize<'de> for RestorationData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = RestorationData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a Uint8List")
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(RestorationData(v))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(RestorationData(v.to_vec()))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+impl Serialize for RestorationData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+/// The reply to [`RestorationMessage::Get`].
+#[derive(Serialize)]
+struct RestorationState<'a> {
+    enabled: bool,
+    data: Option<&'a RestorationData>,
+}
+
+/// Keeps the restoration data of the framework, so it survives restarting
+/// the engine.
+#[derive(Default)]
+pub(super) struct RestorationHandler {
+    data: Option<RestorationData>,
+}
+
+impl RestorationHandler {
+    pub(super) fn new(data: Option<Vec<u8>>) -> Self {
+        Self {
+            data: data.map(RestorationData),
+        }
+    }
+
+    pub(super) fn data(&self) -> Option<&[u8]> {
+        self.data.as_ref().map(|data| data.0.as_slice())
+    }
+
+    /// Returns the encoded success envelope for the reply.
+    pub(super) fn handle_message(
+        &mut self,
+        message: RestorationMessage,
+    ) -> Result<Vec<u8>, message_codec::Error> {
+        match message {
+            RestorationMessage::Get(()) => message_codec::to_success_envelope(&RestorationState {
+                enabled: true,
+                data: self.data.as_ref(),
+            }),
+            RestorationMessage::Put(data) => {
+                self.data = Some(data);
+                message_codec::to_success_envelope(&())
+            }
+        }
+    }
+}
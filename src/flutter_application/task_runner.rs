@@ -1,4 +1,4 @@
-use std::{ffi::c_void, thread::ThreadId, time::Duration};
+use std::{ffi::c_void, rc::Rc, thread::ThreadId, time::Duration};
 
 use tokio::{
     runtime::Builder,
@@ -28,12 +28,14 @@ pub(super) struct TaskRunner {
 }
 
 impl TaskRunner {
-    pub(super) fn new(name: String) -> Self {
+    /// `on_task_started` is called on the runner's thread before every task.
+    pub(super) fn new(name: String, on_task_started: impl Fn() + Send + 'static) -> Self {
         let (new_sender, new_receiver) = oneshot::channel::<SendFlutterEngine>();
         let (sender, mut receiver) = mpsc::unbounded_channel::<Task>();
         let join_handle = std::thread::Builder::new()
             .name(name.clone())
             .spawn(move || {
+                let on_task_started = Rc::new(on_task_started);
                 let engine = new_receiver.blocking_recv().unwrap();
                 let rt = Builder::new_current_thread().enable_time().build().unwrap();
                 let local = LocalSet::new();
@@ -46,13 +48,16 @@ impl TaskRunner {
                     {
                         let now = FlutterApplication::current_time();
                         if now >= target_time_nanos {
+                            on_task_started();
                             FlutterApplication::unwrap_result(unsafe {
                                 FlutterEngineRunTask(engine.0, &task.0)
                             });
                         } else {
+                            let on_task_started = on_task_started.clone();
                             tokio::task::spawn_local(async move {
                                 tokio::time::sleep(Duration::from_nanos(target_time_nanos - now))
                                     .await;
+                                on_task_started();
                                 FlutterApplication::unwrap_result(unsafe {
                                     FlutterEngineRunTask(engine.0, &task.0)
                                 });
@@ -104,6 +104,33 @@ pub(super) enum TextInput {
     /// be called at any time. See [TextInputConnection.close].
     #[serde(rename = "TextInput.hide")]
     Hide,
+    /// The size of the text field and its transform to the coordinate space
+    /// of the view, sent whenever one of them changes.
+    #[serde(rename = "TextInput.setEditableSizeAndTransform")]
+    SetEditableSizeAndTransform(EditableSizeAndTransform),
+    /// The bounds of the text that is being composed, in the coordinate space
+    /// of the text field.
+    #[serde(rename = "TextInput.setMarkedTextRect")]
+    SetMarkedTextRect(TextRect),
+    /// The bounds of the caret, in the coordinate space of the text field.
+    #[serde(rename = "TextInput.setCaretRect")]
+    SetCaretRect(TextRect),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(super) struct EditableSizeAndTransform {
+    pub(super) width: f64,
+    pub(super) height: f64,
+    /// A 4x4 matrix in column-major order.
+    pub(super) transform: [f64; 16],
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub(super) struct TextRect {
+    pub(super) x: f64,
+    pub(super) y: f64,
+    pub(super) width: f64,
+    pub(super) height: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -0,0 +1,217 @@
+//! Turns winit's touches into Flutter pointers. Every finger gets its own
+//! virtual device, which is added when it touches down and removed when it's
+//! lifted, so each contact is a complete add/down/move/up/remove sequence.
+
+use std::collections::HashMap;
+
+use winit::event::{DeviceId, Touch, TouchPhase};
+
+use super::{PointerState, TOUCH_CONTACT};
+use crate::flutter_bindings::{
+    FlutterPointerDeviceKind_kFlutterPointerDeviceKindTouch, FlutterPointerPhase,
+    FlutterPointerPhase_kAdd, FlutterPointerPhase_kCancel, FlutterPointerPhase_kDown,
+    FlutterPointerPhase_kMove, FlutterPointerPhase_kRemove, FlutterPointerPhase_kUp,
+};
+
+#[derive(Default)]
+pub(super) struct Touches {
+    /// By device and finger.
+    fingers: HashMap<(DeviceId, u64), PointerState>,
+}
+
+impl Touches {
+    /// Returns the pointer events for `touch`, in the order they have to be
+    /// sent. `next_pointer_id` is the virtual device id shared with the other
+    /// kinds of pointers, and is advanced for every new finger.
+    ///
+    /// Moves and lifts of fingers that never touched down are ignored.
+    pub(super) fn update(
+        &mut self,
+        touch: &Touch,
+        next_pointer_id: &mut i32,
+    ) -> Vec<(PointerState, FlutterPointerPhase)> {
+        let key = (touch.device_id, touch.id);
+        match touch.phase {
+            TouchPhase::Started => {
+                let mut pointer = PointerState {
+                    virtual_id: *next_pointer_id,
+                    position: touch.location,
+                    held_buttons: 0,
+                    device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindTouch,
+                    pressure: None,
+                    tilt: 0.0,
+                    pan_zoom: None,
+                };
+                pointer.update_force(touch.force);
+                *next_pointer_id += 1;
+                let added = pointer;
+                pointer.held_buttons = TOUCH_CONTACT;
+                self.fingers.insert(key, pointer);
+                vec![
+                    (added, FlutterPointerPhase_kAdd),
+                    (pointer, FlutterPointerPhase_kDown),
+                ]
+            }
+            TouchPhase::Moved => match self.fingers.get_mut(&key) {
+                Some(pointer) => {
+                    pointer.position = touch.location;
+                    pointer.update_force(touch.force);
+                    vec![(*pointer, FlutterPointerPhase_kMove)]
+                }
+                None => Vec::new(),
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => match self.fingers.remove(&key) {
+                Some(mut pointer) => {
+                    pointer.position = touch.location;
+                    pointer.update_force(touch.force);
+                    let phase = if touch.phase == TouchPhase::Ended {
+                        FlutterPointerPhase_kUp
+                    } else {
+                        FlutterPointerPhase_kCancel
+                    };
+                    // The finger doesn't press anything anymore once it's
+                    // lifted, like a mouse reports its released button.
+                    pointer.held_buttons = 0;
+                    vec![(pointer, phase), (pointer, FlutterPointerPhase_kRemove)]
+                }
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+
+    use super::*;
+
+    struct Fingers {
+        touches: Touches,
+        next_pointer_id: i32,
+    }
+
+    impl Fingers {
+        fn new(next_pointer_id: i32) -> Self {
+            Self {
+                touches: Touches::default(),
+                next_pointer_id,
+            }
+        }
+
+        /// The virtual device, phase, buttons and x coordinate of every event.
+        fn update(
+            &mut self,
+            id: u64,
+            phase: TouchPhase,
+            x: f64,
+        ) -> Vec<(i32, FlutterPointerPhase, u64, f64)> {
+            let touch = Touch {
+                // Not an actual device, the touches only need a consistent id.
+                device_id: unsafe { DeviceId::dummy() },
+                phase,
+                location: PhysicalPosition::new(x, 0.0),
+                force: None,
+                id,
+            };
+            self.touches
+                .update(&touch, &mut self.next_pointer_id)
+                .into_iter()
+                .map(|(pointer, phase)| {
+                    (
+                        pointer.virtual_id,
+                        phase,
+                        pointer.held_buttons,
+                        pointer.position.x,
+                    )
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn a_tap_is_a_complete_sequence() {
+        let mut fingers = Fingers::new(3);
+        assert_eq!(
+            fingers.update(0, TouchPhase::Started, 1.0),
+            [
+                (3, FlutterPointerPhase_kAdd, 0, 1.0),
+                (3, FlutterPointerPhase_kDown, TOUCH_CONTACT, 1.0),
+            ]
+        );
+        assert_eq!(fingers.next_pointer_id, 4);
+        assert_eq!(
+            fingers.update(0, TouchPhase::Moved, 2.0),
+            [(3, FlutterPointerPhase_kMove, TOUCH_CONTACT, 2.0)]
+        );
+        assert_eq!(
+            fingers.update(0, TouchPhase::Ended, 3.0),
+            [
+                (3, FlutterPointerPhase_kUp, 0, 3.0),
+                (3, FlutterPointerPhase_kRemove, 0, 3.0),
+            ]
+        );
+        assert!(fingers.touches.fingers.is_empty());
+    }
+
+    #[test]
+    fn fingers_are_separate_devices() {
+        let mut fingers = Fingers::new(0);
+        fingers.update(7, TouchPhase::Started, 1.0);
+        fingers.update(8, TouchPhase::Started, 2.0);
+        assert_eq!(fingers.next_pointer_id, 2);
+        assert_eq!(
+            fingers.update(8, TouchPhase::Moved, 4.0),
+            [(1, FlutterPointerPhase_kMove, TOUCH_CONTACT, 4.0)]
+        );
+        // Lifting the first finger leaves the second one in contact.
+        assert_eq!(
+            fingers.update(7, TouchPhase::Ended, 1.0),
+            [
+                (0, FlutterPointerPhase_kUp, 0, 1.0),
+                (0, FlutterPointerPhase_kRemove, 0, 1.0),
+            ]
+        );
+        assert_eq!(
+            fingers.update(8, TouchPhase::Moved, 5.0),
+            [(1, FlutterPointerPhase_kMove, TOUCH_CONTACT, 5.0)]
+        );
+        // The same finger touching down again is a new device.
+        fingers.update(7, TouchPhase::Started, 6.0);
+        assert_eq!(
+            fingers.update(7, TouchPhase::Moved, 7.0),
+            [(2, FlutterPointerPhase_kMove, TOUCH_CONTACT, 7.0)]
+        );
+    }
+
+    #[test]
+    fn cancelling_removes_the_device() {
+        let mut fingers = Fingers::new(0);
+        fingers.update(0, TouchPhase::Started, 1.0);
+        fingers.update(1, TouchPhase::Started, 2.0);
+        assert_eq!(
+            fingers.update(0, TouchPhase::Cancelled, 3.0),
+            [
+                (0, FlutterPointerPhase_kCancel, 0, 3.0),
+                (0, FlutterPointerPhase_kRemove, 0, 3.0),
+            ]
+        );
+        assert!(fingers.update(0, TouchPhase::Moved, 4.0).is_empty());
+        assert_eq!(
+            fingers.update(1, TouchPhase::Ended, 2.0),
+            [
+                (1, FlutterPointerPhase_kUp, 0, 2.0),
+                (1, FlutterPointerPhase_kRemove, 0, 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fingers_are_ignored() {
+        let mut fingers = Fingers::new(0);
+        for phase in [TouchPhase::Moved, TouchPhase::Ended, TouchPhase::Cancelled] {
+            assert!(fingers.update(0, phase, 1.0).is_empty());
+        }
+        assert_eq!(fingers.next_pointer_id, 0);
+    }
+}
@@ -0,0 +1,418 @@
+//! Hands backing store images back and forth between Skia and wgpu.
+//!
+//! The engine waits for its rendering to finish on the host before calling
+//! the present callback, and both sides submit to the same `VkQueue`, so no
+//! semaphores are needed. What's missing is agreement on image layouts: Skia
+//! leaves the images in `COLOR_ATTACHMENT_OPTIMAL` and expects them there on
+//! the next frame, while wgpu only knows about its own usage tracking. wgpu
+//! doesn't let us record raw barriers into its command encoders, so the
+//! transitions are submitted as separate command buffers on the raw queue,
+//! right before and after the wgpu submission that reads the images.
+//!
+//! A `VkQueue` must not be used from two threads at once. wgpu-core holds
+//! the lock of the device for writing while it submits, and
+//! `Device::as_hal` holds it for reading, so the raw submissions happen
+//! inside of `as_hal`.
+//!
+//! For this to line up, wgpu has to believe a backing store is in the
+//! `COPY_SRC` state (`TRANSFER_SRC_OPTIMAL`) the whole time, see
+//! [`prepare_backing_store`].
+
+use ash::vk;
+use wgpu::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, ImageSubresourceRange, Origin3d, Queue, Texture,
+    TextureAspect,
+};
+use wgpu_hal::api::Vulkan;
+
+/// The layout Skia leaves the backing stores in after rendering.
+const SKIA_LAYOUT: vk::ImageLayout = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+/// The layout wgpu uses for `TextureUses::COPY_SRC`.
+const WGPU_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+
+/// Initializes a new backing store and moves it into the `COPY_SRC` state
+/// from wgpu's point of view. Otherwise wgpu would zero the texture on first
+/// use (discarding what Flutter rendered) and insert its own transitions
+/// from a layout the image isn't actually in.
+///
+/// The engine starts rendering from `UNDEFINED`, which is compatible with
+/// any actual layout.
+pub(super) fn prepare_backing_store(device: &Device, queue: &Queue, texture: &Texture) {
+    let scratch = device.create_buffer(&BufferDescriptor {
+        label: Some("Flutter Backing Store Init"),
+        size: 4,
+        usage: BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+    encoder.clear_texture(texture, &ImageSubresourceRange::default());
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &scratch,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: None,
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+}
+
+/// A fence that is only waited on while a submission is going to signal it,
+/// so a frame that failed halfway can't block the next one, or dropping,
+/// forever.
+struct FrameFence {
+    fence: vk::Fence,
+    in_flight: bool,
+}
+
+impl FrameFence {
+    /// Waits for the last submission signaling the fence, if there is one.
+    fn wait(
+        &mut self,
+        wait: impl FnOnce(vk::Fence) -> Result<(), vk::Result>,
+    ) -> Result<(), vk::Result> {
+        if self.in_flight {
+            wait(self.fence)?;
+            self.in_flight = false;
+        }
+        Ok(())
+    }
+
+    /// Resets the fence right before `submit` makes it signaled again. Has
+    /// to be waited for before.
+    fn submit(
+        &mut self,
+        reset: impl FnOnce(vk::Fence) -> Result<(), vk::Result>,
+        submit: impl FnOnce(vk::Fence) -> Result<(), vk::Result>,
+    ) -> Result<(), vk::Result> {
+        reset(self.fence)?;
+        submit(self.fence)?;
+        self.in_flight = true;
+        Ok(())
+    }
+}
+
+struct FrameSync {
+    pool: vk::CommandPool,
+    acquire: vk::CommandBuffer,
+    release: vk::CommandBuffer,
+    /// Signaled when `release` has finished, so the pool can be reused.
+    fence: FrameFence,
+}
+
+/// Records and submits the layout transitions around the composition.
+pub(super) struct VulkanSync {
+    device: ash::Device,
+    queue: vk::Queue,
+    frames: Vec<FrameSync>,
+    next_frame: usize,
+}
+
+impl VulkanSync {
+    /// `frames_in_flight` sets how many frames can be recorded before
+    /// having to wait for the GPU.
+    pub(super) fn new(device: &Device, frames_in_flight: u32) -> Self {
+        let (raw_device, queue, queue_family_index) = unsafe {
+            device.as_hal::<Vulkan, _, _>(|device| {
+                device.map(|device| {
+                    (
+                        device.raw_device().clone(),
+                        device.raw_queue(),
+                        device.queue_family_index(),
+                    )
+                })
+            })
+        }
+        .expect("wgpu didn't choose Vulkan as rendering backend");
+
+        let frames = (0..frames_in_flight.max(1) + 1)
+            .map(|_| unsafe {
+                let pool = raw_device
+                    .create_command_pool(
+                        &vk::CommandPoolCreateInfo::builder()
+                            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                            .queue_family_index(queue_family_index),
+                        None,
+                    )
+                    .expect("Failed to create command pool");
+                let command_buffers = raw_device
+                    .allocate_command_buffers(
+                        &vk::CommandBufferAllocateInfo::builder()
+                            .command_pool(pool)
+                            .level(vk::CommandBufferLevel::PRIMARY)
+                            .command_buffer_count(2),
+                    )
+                    .expect("Failed to allocate command buffers");
+                let fence = raw_device
+                    .create_fence(&vk::FenceCreateInfo::default(), None)
+                    .expect("Failed to create fence");
+                FrameSync {
+                    pool,
+                    acquire: command_buffers[0],
+                    release: command_buffers[1],
+                    fence: FrameFence {
+                        fence,
+                        in_flight: false,
+                    },
+                }
+            })
+            .collect();
+
+        Self {
+            device: raw_device,
+            queue,
+            frames,
+            next_frame: 0,
+        }
+    }
+
+    /// Transitions `images` to the layout wgpu expects. Has to be called
+    /// before submitting the wgpu commands reading them, followed by
+    /// [`Self::release`] with the same images afterwards.
+    pub(super) fn acquire(
+        &mut self,
+        device: &Device,
+        images: &[vk::Image],
+    ) -> Result<(), vk::Result> {
+        let raw_device = &self.device;
+        let frame = &mut self.frames[self.next_frame];
+        frame
+            .fence
+            .wait(|fence| unsafe { raw_device.wait_for_fences(&[fence], true, u64::MAX) })?;
+        unsafe {
+            raw_device.reset_command_pool(frame.pool, vk::CommandPoolResetFlags::empty())?;
+        }
+        let acquire = frame.acquire;
+        self.record(
+            acquire,
+            images,
+            (SKIA_LAYOUT, WGPU_LAYOUT),
+            (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+        )?;
+        Self::submit(
+            &self.device,
+            self.queue,
+            device,
+            &[acquire],
+            vk::Fence::null(),
+        )
+    }
+
+    /// Hands `images` back to Skia in the layout it left them in.
+    pub(super) fn release(
+        &mut self,
+        device: &Device,
+        images: &[vk::Image],
+    ) -> Result<(), vk::Result> {
+        let frame = self.next_frame;
+        self.next_frame = (frame + 1) % self.frames.len();
+        let release = self.frames[frame].release;
+        let result = self
+            .record(
+                release,
+                images,
+                (WGPU_LAYOUT, SKIA_LAYOUT),
+                (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::empty()),
+                (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ),
+            )
+            .and_then(|()| self.submit_fenced(device, frame, &[release]));
+        if result.is_err() {
+            // The acquire transitions might still be running, and the pool
+            // must not be reset before the queue got past them.
+            self.submit_fenced(device, frame, &[]).ok();
+        }
+        result
+    }
+
+    /// Submits `command_buffers` to signal the fence of `frame` once they
+    /// have finished.
+    fn submit_fenced(
+        &mut self,
+        device: &Device,
+        frame: usize,
+        command_buffers: &[vk::CommandBuffer],
+    ) -> Result<(), vk::Result> {
+        let (raw_device, queue) = (&self.device, self.queue);
+        self.frames[frame].fence.submit(
+            |fence| unsafe { raw_device.reset_fences(&[fence]) },
+            |fence| Self::submit(raw_device, queue, device, command_buffers, fence),
+        )
+    }
+
+    fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        images: &[vk::Image],
+        (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+        (src_stage, src_access): (vk::PipelineStageFlags, vk::AccessFlags),
+        (dst_stage, dst_access): (vk::PipelineStageFlags, vk::AccessFlags),
+    ) -> Result<(), vk::Result> {
+        let barriers: Vec<vk::ImageMemoryBarrier> = images
+            .iter()
+            .map(|&image| {
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .old_layout(old_layout)
+                    .new_layout(new_layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .build()
+            })
+            .collect();
+        unsafe {
+            self.device.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )?;
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers,
+            );
+            self.device.end_command_buffer(command_buffer)
+        }
+    }
+
+    /// Submits while holding wgpu's lock of `device`, which it also takes to
+    /// submit to the queue.
+    fn submit(
+        raw_device: &ash::Device,
+        queue: vk::Queue,
+        device: &Device,
+        command_buffers: &[vk::CommandBuffer],
+        fence: vk::Fence,
+    ) -> Result<(), vk::Result> {
+        let submit_info = [vk::SubmitInfo::builder()
+            .command_buffers(command_buffers)
+            .build()];
+        unsafe {
+            device.as_hal::<Vulkan, _, _>(|_| raw_device.queue_submit(queue, &submit_info, fence))
+        }
+    }
+}
+
+impl Drop for VulkanSync {
+    fn drop(&mut self) {
+        unsafe {
+            for frame in &mut self.frames {
+                // Ignore errors, the device might be lost already.
+                let raw_device = &self.device;
+                frame
+                    .fence
+                    .wait(|fence| raw_device.wait_for_fences(&[fence], true, u64::MAX))
+                    .ok();
+                self.device.destroy_fence(frame.fence.fence, None);
+                self.device.destroy_command_pool(frame.pool, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fence() -> FrameFence {
+        FrameFence {
+            fence: vk::Fence::null(),
+            in_flight: false,
+        }
+    }
+
+    fn never(_: vk::Fence) -> Result<(), vk::Result> {
+        panic!("Waiting on a fence nothing is going to signal")
+    }
+
+    #[test]
+    fn submitted_fences_are_waited_for_once() {
+        let mut fence = fence();
+        fence.submit(|_| Ok(()), |_| Ok(())).unwrap();
+        let mut waits = 0;
+        fence
+            .wait(|_| {
+                waits += 1;
+                Ok(())
+            })
+            .unwrap();
+        fence.wait(never).unwrap();
+        assert_eq!(waits, 1);
+    }
+
+    #[test]
+    fn failed_submissions_are_not_waited_for() {
+        let mut fence = fence();
+        assert_eq!(
+            fence.submit(|_| Ok(()), |_| Err(vk::Result::ERROR_DEVICE_LOST)),
+            Err(vk::Result::ERROR_DEVICE_LOST)
+        );
+        fence.wait(never).unwrap();
+    }
+
+    #[test]
+    fn failed_resets_are_not_waited_for() {
+        let mut fence = fence();
+        let result = fence.submit(
+            |_| Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+            |_| panic!("Submitted with a fence that wasn't reset"),
+        );
+        assert_eq!(result, Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY));
+        fence.wait(never).unwrap();
+    }
+
+    #[test]
+    fn failed_waits_are_repeated() {
+        let mut fence = fence();
+        fence.submit(|_| Ok(()), |_| Ok(())).unwrap();
+        assert_eq!(
+            fence.wait(|_| Err(vk::Result::ERROR_DEVICE_LOST)),
+            Err(vk::Result::ERROR_DEVICE_LOST)
+        );
+        let mut waited = false;
+        fence
+            .wait(|_| {
+                waited = true;
+                Ok(())
+            })
+            .unwrap();
+        assert!(waited);
+    }
+}
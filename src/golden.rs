@@ -0,0 +1,500 @@
+//! Renders a Flutter bundle offscreen and compares the result against a
+//! stored golden image.
+//!
+//! A hidden window is still created, since the application needs one for
+//! cursors and text input, so an X server (for example Xvfb) is required.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::runtime::Runtime;
+use wgpu::{
+    Backends, DeviceDescriptor, Features, Instance, Limits, PowerPreference, RequestAdapterOptions,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::Event,
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder},
+    platform::run_return::EventLoopExtRunReturn,
+    window::WindowBuilder,
+};
+
+use crate::flutter_application::{
+    FlutterApplication, FlutterApplicationCallback, PresentationConfig, RenderTarget, TextureTarget,
+};
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Decoding(png::DecodingError),
+    Encoding(png::EncodingError),
+    /// The golden image isn't 8 bit RGBA.
+    UnsupportedFormat(png::ColorType, png::BitDepth),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The application didn't settle within the timeout.
+    Timeout,
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Decoding(err) => write!(f, "{err}"),
+            Self::Encoding(err) => write!(f, "{err}"),
+            Self::UnsupportedFormat(color_type, bit_depth) => write!(
+                f,
+                "Unsupported golden image format {color_type:?} {bit_depth:?}"
+            ),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "Golden image is {}x{}, but the rendered frame is {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Self::Timeout => write!(f, "Timed out waiting for the frame to become idle"),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<io::Error> for GoldenError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for GoldenError {
+    fn from(err: png::DecodingError) -> Self {
+        Self::Decoding(err)
+    }
+}
+
+impl From<png::EncodingError> for GoldenError {
+    fn from(err: png::EncodingError) -> Self {
+        Self::Encoding(err)
+    }
+}
+
+/// An 8 bit RGBA image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl GoldenImage {
+    fn from_bgra(width: u32, height: u32, mut pixels: Vec<u8>) -> Self {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GoldenError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels)?;
+        let pixels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGBA, png::BitDepth::Eight) => pixels,
+            (png::ColorType::RGB, png::BitDepth::Eight) => pixels
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xff])
+                .collect(),
+            (color_type, bit_depth) => {
+                return Err(GoldenError::UnsupportedFormat(color_type, bit_depth))
+            }
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GoldenError> {
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    /// Compares every channel of every pixel. Differences up to `tolerance`
+    /// are accepted.
+    pub fn compare(&self, expected: &Self, tolerance: u8) -> Result<GoldenComparison, GoldenError> {
+        if (self.width, self.height) != (expected.width, expected.height) {
+            return Err(GoldenError::SizeMismatch {
+                expected: (expected.width, expected.height),
+                actual: (self.width, self.height),
+            });
+        }
+        let mut mismatched_pixels = 0;
+        let mut max_difference = 0;
+        let mut diff = Vec::with_capacity(self.pixels.len());
+        for (actual, expected) in self
+            .pixels
+            .chunks_exact(4)
+            .zip(expected.pixels.chunks_exact(4))
+        {
+            let difference = actual
+                .iter()
+                .zip(expected)
+                .map(|(&a, &e)| a.abs_diff(e))
+                .max()
+                .unwrap_or(0);
+            max_difference = max_difference.max(difference);
+            if difference > tolerance {
+                mismatched_pixels += 1;
+                diff.extend_from_slice(&[0xff, 0x00, 0xff, 0xff]);
+            } else {
+                // Dimmed grayscale, so the mismatches stand out.
+                let luma = (actual[0] as u32 * 3 + actual[1] as u32 * 6 + actual[2] as u32) / 10;
+                let luma = (luma / 4) as u8;
+                diff.extend_from_slice(&[luma, luma, luma, 0xff]);
+            }
+        }
+        Ok(GoldenComparison {
+            mismatched_pixels,
+            max_difference,
+            diff: (mismatched_pixels > 0).then(|| Self {
+                width: self.width,
+                height: self.height,
+                pixels: diff,
+            }),
+        })
+    }
+}
+
+pub struct GoldenComparison {
+    pub mismatched_pixels: usize,
+    /// The largest difference of a single channel over the whole image.
+    pub max_difference: u8,
+    /// Mismatching pixels in magenta on top of a dimmed copy of the
+    /// rendered frame. `None` if the images match.
+    pub diff: Option<GoldenImage>,
+}
+
+impl GoldenComparison {
+    pub fn matches(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
+
+/// Boots a bundle with fixed metrics, so the output is reproducible.
+pub struct GoldenTest {
+    pub asset_bundle_path: PathBuf,
+    pub flutter_flags: Vec<String>,
+    /// Physical size of the view in pixels.
+    pub width: u32,
+    pub height: u32,
+    pub device_pixel_ratio: f64,
+    /// BCP 47 language tag, like `en-US`.
+    pub locale: String,
+    /// The frame counts as idle once no new frame was presented for this long.
+    pub idle_duration: Duration,
+    /// Give up if the frame isn't idle after this long.
+    pub timeout: Duration,
+}
+
+impl GoldenTest {
+    pub fn new(asset_bundle_path: PathBuf) -> Self {
+        Self {
+            asset_bundle_path,
+            flutter_flags: vec![],
+            width: 800,
+            height: 600,
+            device_pixel_ratio: 1.0,
+            locale: "en-US".to_owned(),
+            idle_duration: Duration::from_millis(500),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Renders until the frame is idle and returns the composited image.
+    ///
+    /// Can only be called once per process, since winit doesn't support
+    /// creating more than one event loop.
+    pub fn capture(&self, runtime: Arc<Runtime>) -> Result<GoldenImage, GoldenError> {
+        let (mut event_loop, mut app) = self.start(runtime);
+        self.wait_until_idle(&mut event_loop, &mut app)?;
+
+        let pixels = match app.render_target() {
+            RenderTarget::Texture(target) => {
+                target.read_pixels(app.device(), app.queue(), self.width, self.height)
+            }
+            RenderTarget::Surface(_) => unreachable!(),
+        };
+        Ok(GoldenImage::from_bgra(self.width, self.height, pixels))
+    }
+
+    /// Boots the bundle into an offscreen texture. Nothing happens until the
+    /// returned event loop is run.
+    ///
+    /// Can only be called once per process, since winit doesn't support
+    /// creating more than one event loop.
+    pub fn start(
+        &self,
+        runtime: Arc<Runtime>,
+    ) -> (EventLoop<FlutterApplicationCallback>, FlutterApplication) {
+        let event_loop: EventLoop<FlutterApplicationCallback> =
+            EventLoopBuilder::with_user_event().build();
+        let window = Arc::new(
+            WindowBuilder::new()
+                .with_title("Flutter Golden Test")
+                .with_inner_size(PhysicalSize::new(self.width, self.height))
+                .with_visible(false)
+                .build(&event_loop)
+                .unwrap(),
+        );
+
+        let instance = Instance::new(Backends::VULKAN);
+        let (device, queue) = runtime.block_on(async {
+            let adapter = instance
+                .request_adapter(&RequestAdapterOptions {
+                    power_preference: PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: false,
+                })
+                .await
+                .unwrap();
+            adapter
+                .request_device(
+                    &DeviceDescriptor {
+                        label: None,
+                        features: Features::CLEAR_TEXTURE,
+                        limits: Limits::downlevel_defaults(),
+                    },
+                    None,
+                )
+                .await
+                .expect("Failed to create device")
+        });
+
+        let texture = Arc::new(TextureTarget::create_texture(
+            &device,
+            self.width,
+            self.height,
+        ));
+        let mut app = FlutterApplication::new(
+            runtime,
+            &self.asset_bundle_path,
+            self.flutter_flags.clone(),
+            RenderTarget::Texture(TextureTarget::new(texture, self.width, self.height, |_| {})),
+            PresentationConfig::default(),
+            Arc::new(instance),
+            device,
+            queue,
+            event_loop.create_proxy(),
+            window,
+            |_| {},
+        );
+
+        app.run();
+        app.update_locales(&[&self.locale]);
+        app.metrics_changed(self.width, self.height, self.device_pixel_ratio);
+        (event_loop, app)
+    }
+
+    /// Runs the event loop until no new frame was presented for
+    /// `idle_duration`.
+    pub fn wait_until_idle(
+        &self,
+        event_loop: &mut EventLoop<FlutterApplicationCallback>,
+        app: &mut FlutterApplication,
+    ) -> Result<(), GoldenError> {
+        let poll_interval = self.idle_duration / 4;
+        let deadline = Instant::now() + self.timeout;
+        let idle_nanos = self.idle_duration.as_nanos() as u64;
+        let mut timed_out = false;
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(Instant::now() + poll_interval);
+            if let Event::UserEvent(handler) = event {
+                if handler(app) {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+            if let Some(last_present) = app.last_present_time() {
+                if FlutterApplication::current_time().saturating_sub(last_present) >= idle_nanos {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+            if Instant::now() >= deadline {
+                timed_out = true;
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+        if timed_out {
+            Err(GoldenError::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Captures a frame and compares it to the PNG at `golden_path`. With
+    /// `update`, the golden is (re)written instead. On mismatch, a diff image
+    /// is written next to the golden with a `.diff.png` extension.
+    pub fn run(
+        &self,
+        runtime: Arc<Runtime>,
+        golden_path: &Path,
+        tolerance: u8,
+        update: bool,
+    ) -> Result<GoldenComparison, GoldenError> {
+        let actual = self.capture(runtime)?;
+        if update {
+            actual.save(golden_path)?;
+            return Ok(GoldenComparison {
+                mismatched_pixels: 0,
+                max_difference: 0,
+                diff: None,
+            });
+        }
+        let comparison = actual.compare(&GoldenImage::load(golden_path)?, tolerance)?;
+        if let Some(diff) = &comparison.diff {
+            diff.save(&golden_path.with_extension("diff.png"))?;
+        }
+        Ok(comparison)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: &[[u8; 4]]) -> GoldenImage {
+        GoldenImage {
+            width: pixels.len() as u32,
+            height: 1,
+            pixels: pixels.concat(),
+        }
+    }
+
+    /// A path in the temporary directory that is removed on drop.
+    struct TempPng(PathBuf);
+
+    impl TempPng {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("golden-{}-{name}.png", std::process::id())))
+        }
+    }
+
+    impl Drop for TempPng {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn from_bgra_swaps_red_and_blue() {
+        let image = GoldenImage::from_bgra(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(image.pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let actual = image(&[[10, 20, 30, 255], [0, 0, 0, 0]]);
+        let comparison = actual.compare(&actual.clone(), 0).unwrap();
+        assert!(comparison.matches());
+        assert_eq!(comparison.max_difference, 0);
+        assert!(comparison.diff.is_none());
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_match() {
+        let actual = image(&[[10, 20, 30, 255]]);
+        let expected = image(&[[12, 18, 30, 255]]);
+        let comparison = actual.compare(&expected, 2).unwrap();
+        assert!(comparison.matches());
+        assert_eq!(comparison.max_difference, 2);
+        assert!(!actual.compare(&expected, 1).unwrap().matches());
+    }
+
+    #[test]
+    fn mismatches_are_magenta_in_the_diff() {
+        let actual = image(&[[40, 80, 120, 255], [0, 0, 0, 255]]);
+        let expected = image(&[[40, 80, 120, 255], [0, 0, 9, 255]]);
+        let comparison = actual.compare(&expected, 0).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 1);
+        assert_eq!(comparison.max_difference, 9);
+        let luma = ((40 * 3 + 80 * 6 + 120) / 10 / 4) as u8;
+        assert_eq!(
+            comparison.diff,
+            Some(image(&[[luma, luma, luma, 255], [255, 0, 255, 255]]))
+        );
+    }
+
+    #[test]
+    fn different_sizes_dont_compare() {
+        let actual = image(&[[0; 4]; 2]);
+        let expected = image(&[[0; 4]; 3]);
+        assert!(matches!(
+            actual.compare(&expected, 255),
+            Err(GoldenError::SizeMismatch {
+                expected: (3, 1),
+                actual: (2, 1),
+            })
+        ));
+    }
+
+    #[test]
+    fn saved_images_load_unchanged() {
+        let path = TempPng::new("round-trip");
+        let image = GoldenImage {
+            width: 2,
+            height: 2,
+            pixels: (0..16).collect(),
+        };
+        image.save(&path.0).unwrap();
+        assert_eq!(GoldenImage::load(&path.0).unwrap(), image);
+    }
+
+    fn write_png(path: &Path, color_type: png::ColorType, pixels: &[u8]) {
+        let mut encoder = png::Encoder::new(File::create(path).unwrap(), 1, 1);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(pixels)
+            .unwrap();
+    }
+
+    #[test]
+    fn rgb_images_load_opaque() {
+        let path = TempPng::new("rgb");
+        write_png(&path.0, png::ColorType::RGB, &[1, 2, 3]);
+        assert_eq!(
+            GoldenImage::load(&path.0).unwrap(),
+            image(&[[1, 2, 3, 255]])
+        );
+    }
+
+    #[test]
+    fn grayscale_images_are_unsupported() {
+        let path = TempPng::new("grayscale");
+        write_png(&path.0, png::ColorType::Grayscale, &[1]);
+        assert!(matches!(
+            GoldenImage::load(&path.0),
+            Err(GoldenError::UnsupportedFormat(png::ColorType::Grayscale, _))
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+use winit::keyboard::KeyCode;
+
+// Generated by build.rs from keymaps.csv.
+include!(concat!(env!("OUT_DIR"), "/keyboard_gtk_key_map.rs"));
+
+/// Returns the X11 hardware keycode and the keysym of the unmodified key, as
+/// GTK reports them in `GdkEventKey::hardware_keycode` and `keyval`. The
+/// keysym is 0 for keys without a standard one.
+pub fn translate_gtk_key(code: KeyCode) -> Option<(u64, u64)> {
+    gtk_key_codes(code)
+}
+
+/// Returns the keysym of the dead key that produces `c`, which winit reports
+/// either as the spacing or as the combining accent.
+pub fn dead_key_keysym(c: char) -> Option<u64> {
+    Some(match c {
+        '`' | '\u{300}' => 0xfe50,
+        '´' | '\u{301}' => 0xfe51,
+        '^' | '\u{302}' => 0xfe52,
+        '~' | '\u{303}' => 0xfe53,
+        '¯' | '\u{304}' => 0xfe54,
+        '˘' | '\u{306}' => 0xfe55,
+        '˙' | '\u{307}' => 0xfe56,
+        '¨' | '\u{308}' => 0xfe57,
+        '˚' | '\u{30a}' => 0xfe58,
+        '˝' | '\u{30b}' => 0xfe59,
+        'ˇ' | '\u{30c}' => 0xfe5a,
+        '¸' | '\u{327}' => 0xfe5b,
+        '˛' | '\u{328}' => 0xfe5c,
+        _ => return None,
+    })
+}
@@ -1,30 +1,43 @@
 #![allow(dead_code)]
 #![feature(once_cell, result_option_inspect)]
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
 
-use clap::Parser;
-use tokio::runtime::Builder;
+use clap::{ArgEnum, Parser};
+use tokio::runtime::Runtime;
 use wgpu::{
-    Backends, DeviceDescriptor, Features, Instance, Limits, PowerPreference, PresentMode,
+    Adapter, Backends, DeviceDescriptor, Features, Instance, Limits, PowerPreference, PresentMode,
     RequestAdapterOptions, SurfaceConfiguration, TextureFormat, TextureUsages,
 };
 use winit::{
-    dpi::PhysicalPosition,
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop, EventLoopBuilder},
+    event::{ElementState, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy},
+    keyboard::KeyCode,
     window::{Window, WindowBuilder},
 };
 
 mod flutter_application;
-use flutter_application::{FlutterApplication, FlutterApplicationCallback};
+use flutter_application::{
+    FlutterApplication, FlutterApplicationCallback, PresentationConfig, RenderTarget, ScrollConfig,
+    SurfaceTarget,
+};
 
 mod action_key;
+mod keyboard_gtk_key_map;
 mod keyboard_logical_key_map;
 mod keyboard_physical_key_map;
 
 mod flutter_bindings;
+mod golden;
+mod pointer_benchmark;
 mod utils;
 
+use golden::GoldenTest;
+use pointer_benchmark::PointerBenchmark;
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
@@ -41,121 +54,133 @@ struct Args {
     /// `flutter_tester --help` using the test binary included in the
     /// Flutter tools.
     pub flutter_flags: Vec<String>,
+    /// How frames are handed to the display. `fifo` waits for vsync,
+    /// `mailbox` and `immediate` trade power for lower latency. Falls back to
+    /// `fifo` if the graphics adapter doesn't support the requested mode.
+    #[clap(long, arg_enum, default_value = "fifo")]
+    pub present_mode: PresentModeArg,
+    /// The number of frames that may be queued on the GPU. Lower values
+    /// reduce latency and power usage at the cost of throughput.
+    #[clap(long, default_value = "2")]
+    pub frames_in_flight: u32,
+    /// Show frame timings and resource usage on top of the application.
+    /// F12 then toggles it at runtime, instead of reaching the application.
+    #[clap(long)]
+    pub perf_hud: bool,
+    /// Open the window without title bar and borders.
+    #[clap(long)]
+    pub no_decorations: bool,
+    /// Logical pixels scrolled per mouse wheel notch.
+    #[clap(long, default_value = "40")]
+    pub scroll_pixels_per_line: f64,
+    /// Render offscreen and compare the first idle frame against this PNG
+    /// instead of opening a window. Exits with an error on mismatch and
+    /// writes a diff image next to the golden.
+    #[clap(long)]
+    pub golden: Option<PathBuf>,
+    /// Write the rendered frame to the `--golden` path instead of comparing.
+    #[clap(long, requires = "golden")]
+    pub update_golden: bool,
+    /// Maximum accepted difference per color channel in golden mode.
+    #[clap(long, default_value = "0")]
+    pub golden_tolerance: u8,
+    /// Size of the offscreen view in golden mode, in physical pixels.
+    #[clap(long, default_value = "800")]
+    pub golden_width: u32,
+    #[clap(long, default_value = "600")]
+    pub golden_height: u32,
+    #[clap(long, default_value = "1.0")]
+    pub golden_device_pixel_ratio: f64,
+    #[clap(long, default_value = "en-US")]
+    pub golden_locale: String,
+    /// Render offscreen like in golden mode, then measure sending this many
+    /// pointer events one by one against sending them batched, and exit.
+    #[clap(long, conflicts_with = "golden")]
+    pub bench_pointer_events: Option<usize>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum PresentModeArg {
+    Fifo,
+    Mailbox,
+    Immediate,
 }
 
-fn main() -> Result<(), std::io::Error> {
+impl From<PresentModeArg> for PresentMode {
+    fn from(mode: PresentModeArg) -> Self {
+        match mode {
+            PresentModeArg::Fifo => PresentMode::Fifo,
+            PresentModeArg::Mailbox => PresentMode::Mailbox,
+            PresentModeArg::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+fn main() -> Result<ExitCode, std::io::Error> {
     env_logger::init();
     let args = Args::parse();
 
+    if let Some(golden_path) = &args.golden {
+        return run_golden_test(&args, golden_path);
+    }
+    if let Some(events) = args.bench_pointer_events {
+        return run_pointer_benchmark(&args, events);
+    }
+
     let event_loop: EventLoop<FlutterApplicationCallback> =
         EventLoopBuilder::with_user_event().build();
     let window = WindowBuilder::new()
         .with_title("Flutter Embedder")
+        .with_decorations(!args.no_decorations)
         // .with_inner_size(PhysicalSize::new(1024, 768))
         .build(&event_loop)
         .unwrap();
     // window.set_outer_position(PhysicalPosition::new(100, 100));
 
-    let rt = Arc::new(Builder::new_multi_thread().build()?);
+    let rt = FlutterApplication::create_runtime()?;
     let inner_rt = rt.clone();
 
-    rt.block_on(async move {
-        let instance = Instance::new(Backends::VULKAN);
-        let surface = unsafe { instance.create_surface(&window) };
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    label: None,
-                    features: Features::CLEAR_TEXTURE,
-                    limits: Limits::downlevel_defaults(),
-                },
-                None,
-            )
-            .await
-            .expect("Failed to create device");
-
-        let size = window.inner_size();
-
-        log::debug!(
-            "Supported formats: {:?}",
-            surface.get_supported_formats(&adapter)
-        );
-        let formats = surface.get_supported_formats(&adapter);
-        let format = formats
-            .into_iter()
-            .find(|&format| format == TextureFormat::Bgra8Unorm)
-            .expect("Adapter doesn't support BGRA8 render buffer.");
-
-        surface.configure(
-            &device,
-            &SurfaceConfiguration {
-                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
-                format,
-                width: size.width,
-                height: size.height,
-                present_mode: PresentMode::Fifo,
-            },
-        );
-
-        let window = Arc::new(window);
-        let inner_window = window.clone();
-
-        let mut app = FlutterApplication::new(
-            inner_rt,
-            &args.asset_bundle_path,
-            args.flutter_flags,
-            surface,
-            Arc::new(instance),
-            device,
-            queue,
-            event_loop.create_proxy(),
-            window.clone(),
-            move |cursor| {
-                if let Some(cursor) = cursor {
-                    inner_window.set_cursor_visible(true);
-                    inner_window.set_cursor_icon(cursor);
-                } else {
-                    inner_window.set_cursor_visible(false);
-                }
-            },
-        );
-
-        app.run();
+    let window = Arc::new(window);
+    let event_loop_proxy = event_loop.create_proxy();
 
-        // Trigger a FlutterEngineSendWindowMetricsEvent to communicate the initial
-        // size of the window.
-        metrics_changed(&app, &window);
+    rt.block_on(async move {
+        let mut running =
+            Some(create_application(&inner_rt, &args, &window, &event_loop_proxy, None).await);
 
         event_loop.run(move |event, _, control_flow| {
-            let _ = &adapter;
+            let (_, app) = running.as_mut().unwrap();
 
             *control_flow = ControlFlow::Wait;
             match event {
                 Event::UserEvent(handler) => {
-                    if handler(&mut app) {
+                    if handler(app) {
                         *control_flow = ControlFlow::Exit;
                     }
                 }
                 Event::RedrawRequested(_window_id) => {
                     app.schedule_frame();
                 }
+                Event::MainEventsCleared => {
+                    app.sync_modifiers();
+                    app.flush_pointer_events();
+                }
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
-                    WindowEvent::Moved(_)
-                    | WindowEvent::Resized(_)
-                    | WindowEvent::ScaleFactorChanged { .. } => {
-                        metrics_changed(&app, &window);
+                    WindowEvent::Moved(_) => {
+                        // The window might be on another display now.
+                        app.display_changed();
+                    }
+                    event @ (WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. }) => {
+                        if let Some((width, height, pixel_ratio)) =
+                            view_metrics(&event, window.scale_factor())
+                        {
+                            app.metrics_changed(width, height, pixel_ratio);
+                        }
+                        if let WindowEvent::ScaleFactorChanged { .. } = event {
+                            app.display_changed();
+                        }
                     }
                     WindowEvent::MouseInput {
                         device_id,
@@ -186,9 +211,33 @@ fn main() -> Result<(), std::io::Error> {
                     } => {
                         app.mouse_wheel(device_id, delta, phase);
                     }
+                    WindowEvent::Touch(touch) => {
+                        app.touch(touch);
+                    }
+                    WindowEvent::TouchpadMagnify {
+                        device_id,
+                        delta,
+                        phase,
+                    } => {
+                        app.touchpad_magnify(device_id, delta, phase);
+                    }
+                    WindowEvent::TouchpadRotate {
+                        device_id,
+                        delta,
+                        phase,
+                    } => {
+                        app.touchpad_rotate(device_id, delta, phase);
+                    }
                     WindowEvent::ModifiersChanged(state) => {
                         app.modifiers_changed(state);
                     }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if args.perf_hud && event.physical_key == KeyCode::F12 =>
+                    {
+                        if event.state == ElementState::Pressed && !event.repeat {
+                            app.toggle_perf_hud();
+                        }
+                    }
                     WindowEvent::KeyboardInput {
                         event,
                         device_id,
@@ -196,6 +245,9 @@ fn main() -> Result<(), std::io::Error> {
                     } => {
                         app.key_event(device_id, event, is_synthetic);
                     }
+                    WindowEvent::Ime(ime) => {
+                        app.ime(ime);
+                    }
                     WindowEvent::Focused(focused) => {
                         app.focused(focused);
                     }
@@ -203,31 +255,324 @@ fn main() -> Result<(), std::io::Error> {
                 },
                 _ => {}
             }
+
+            restart_if_device_lost(&mut running, |restoration_data| {
+                tokio::task::block_in_place(|| {
+                    inner_rt.block_on(create_application(
+                        &inner_rt,
+                        &args,
+                        &window,
+                        &event_loop_proxy,
+                        restoration_data,
+                    ))
+                })
+            });
         });
     });
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// What [`restart_if_device_lost`] needs to know about the running
+/// application.
+trait Restartable {
+    fn is_device_lost(&self) -> bool;
+    fn restoration_data(&self) -> Option<Vec<u8>>;
 }
 
-fn metrics_changed(application: &FlutterApplication, window: &Window) {
+impl Restartable for (Adapter, FlutterApplication) {
+    fn is_device_lost(&self) -> bool {
+        self.1.is_device_lost()
+    }
+
+    fn restoration_data(&self) -> Option<Vec<u8>> {
+        self.1.restoration_data()
+    }
+}
+
+/// Replaces the running application with one from `create` once its device
+/// was lost, handing over the restoration data. Vulkan allows only one
+/// swapchain per window, so the old engine and its surface are gone before
+/// `create` sets up the new ones.
+fn restart_if_device_lost<T: Restartable>(
+    running: &mut Option<T>,
+    create: impl FnOnce(Option<Vec<u8>>) -> T,
+) {
+    let restoration_data = match running {
+        Some(app) if app.is_device_lost() => app.restoration_data(),
+        _ => return,
+    };
+    log::warn!("Restarting the engine with a new graphics device");
+    *running = None;
+    *running = Some(create(restoration_data));
+}
+
+/// Sets up a new device and surface and starts the engine on them. Also used
+/// to start over after the device was lost.
+async fn create_application(
+    rt: &Arc<Runtime>,
+    args: &Args,
+    window: &Arc<Window>,
+    event_loop_proxy: &EventLoopProxy<FlutterApplicationCallback>,
+    restoration_data: Option<Vec<u8>>,
+) -> (Adapter, FlutterApplication) {
+    let instance = Instance::new(Backends::VULKAN);
+    let surface = unsafe { instance.create_surface(window.as_ref()) };
+    let adapter = instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::default(),
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        })
+        .await
+        .unwrap();
+
+    let (device, queue) = adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                features: Features::CLEAR_TEXTURE,
+                limits: Limits::downlevel_defaults(),
+            },
+            None,
+        )
+        .await
+        .expect("Failed to create device");
+
     let size = window.inner_size();
-    let position = window
-        .inner_position()
-        .unwrap_or(PhysicalPosition { x: 0, y: 0 });
+
     log::debug!(
-        "scale_factor = {:?}",
-        window.scale_factor(),
-        // window
-        //     .current_monitor()
-        //     .map(|monitor| monitor.scale_factor())
+        "Supported formats: {:?}",
+        surface.get_supported_formats(&adapter)
+    );
+    let formats = surface.get_supported_formats(&adapter);
+    let format = formats
+        .into_iter()
+        .find(|&format| format == TextureFormat::Bgra8Unorm)
+        .expect("Adapter doesn't support BGRA8 render buffer.");
+
+    let presentation = PresentationConfig {
+        present_mode: args.present_mode.into(),
+        frames_in_flight: args.frames_in_flight.max(1),
+    }
+    .supported(&surface.get_supported_modes(&adapter));
+
+    let surface = SurfaceTarget::new(
+        surface,
+        &device,
+        SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: presentation.present_mode,
+        },
     );
-    application.metrics_changed(
-        size.width,
-        size.height,
-        window
-            .current_monitor()
-            .map(|monitor| monitor.scale_factor())
-            .unwrap_or(1.0),
-        position.x,
-        position.y,
+
+    let inner_window = window.clone();
+
+    let mut app = FlutterApplication::new(
+        rt.clone(),
+        &args.asset_bundle_path,
+        args.flutter_flags.clone(),
+        RenderTarget::Surface(surface),
+        presentation,
+        Arc::new(instance),
+        device,
+        queue,
+        event_loop_proxy.clone(),
+        window.clone(),
+        move |cursor| {
+            if let Some(cursor) = cursor {
+                inner_window.set_cursor_visible(true);
+                inner_window.set_cursor_icon(cursor);
+            } else {
+                inner_window.set_cursor_visible(false);
+            }
+        },
     );
+
+    app.set_restoration_data(restoration_data);
+    app.run();
+    app.display_changed();
+    app.set_perf_hud_enabled(args.perf_hud);
+    app.set_scroll_config(ScrollConfig {
+        pixels_per_line: args.scroll_pixels_per_line,
+    });
+
+    // Trigger a FlutterEngineSendWindowMetricsEvent to communicate the initial
+    // size of the window.
+    let size = window.inner_size();
+    app.metrics_changed(size.width, size.height, window.scale_factor());
+
+    (adapter, app)
+}
+
+/// The physical size and pixel ratio of the view after `event`, if it
+/// changed them. `scale_factor` is the one of the window before the event.
+fn view_metrics(event: &WindowEvent, scale_factor: f64) -> Option<(u32, u32, f64)> {
+    match event {
+        WindowEvent::Resized(size) => Some((size.width, size.height, scale_factor)),
+        // The window hasn't been resized yet, so its `inner_size` is still
+        // the old one.
+        WindowEvent::ScaleFactorChanged {
+            scale_factor,
+            new_inner_size,
+        } => Some((new_inner_size.width, new_inner_size.height, *scale_factor)),
+        _ => None,
+    }
+}
+
+fn run_golden_test(args: &Args, golden_path: &Path) -> Result<ExitCode, std::io::Error> {
+    let rt = FlutterApplication::create_runtime()?;
+    let test = GoldenTest {
+        flutter_flags: args.flutter_flags.clone(),
+        width: args.golden_width,
+        height: args.golden_height,
+        device_pixel_ratio: args.golden_device_pixel_ratio,
+        locale: args.golden_locale.clone(),
+        ..GoldenTest::new(args.asset_bundle_path.clone())
+    };
+    match test.run(rt, golden_path, args.golden_tolerance, args.update_golden) {
+        Ok(comparison) if comparison.matches() => Ok(ExitCode::SUCCESS),
+        Ok(comparison) => {
+            log::error!(
+                "{} pixels differ from {golden_path:?} (max channel difference {})",
+                comparison.mismatched_pixels,
+                comparison.max_difference,
+            );
+            Ok(ExitCode::FAILURE)
+        }
+        Err(err) => {
+            log::error!("Golden test failed: {err}");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn run_pointer_benchmark(args: &Args, events: usize) -> Result<ExitCode, std::io::Error> {
+    let rt = FlutterApplication::create_runtime()?;
+    let benchmark = PointerBenchmark {
+        test: GoldenTest {
+            flutter_flags: args.flutter_flags.clone(),
+            width: args.golden_width,
+            height: args.golden_height,
+            device_pixel_ratio: args.golden_device_pixel_ratio,
+            locale: args.golden_locale.clone(),
+            ..GoldenTest::new(args.asset_bundle_path.clone())
+        },
+        events,
+        rounds: 5,
+    };
+    match benchmark.run(rt) {
+        Ok(result) => {
+            println!(
+                "{events} pointer events, best of {} rounds:",
+                benchmark.rounds
+            );
+            for (name, timings) in [
+                ("one by one", result.one_by_one),
+                ("batched", result.batched),
+            ] {
+                println!(
+                    "  {name}: sent after {:?}, dispatched after {:?}",
+                    timings.sent, timings.dispatched
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(err) => {
+            log::error!("Pointer benchmark failed: {err}");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+
+    #[test]
+    fn resizing_keeps_the_scale_factor() {
+        let event = WindowEvent::Resized(PhysicalSize::new(1280, 720));
+        assert_eq!(view_metrics(&event, 1.5), Some((1280, 720, 1.5)));
+    }
+
+    #[test]
+    fn scale_factor_changes_use_the_suggested_size() {
+        let mut new_inner_size = PhysicalSize::new(2560, 1440);
+        let event = WindowEvent::ScaleFactorChanged {
+            scale_factor: 2.0,
+            new_inner_size: &mut new_inner_size,
+        };
+        assert_eq!(view_metrics(&event, 1.0), Some((2560, 1440, 2.0)));
+    }
+
+    #[test]
+    fn other_events_keep_the_metrics() {
+        assert_eq!(view_metrics(&WindowEvent::CloseRequested, 1.0), None);
+        assert_eq!(view_metrics(&WindowEvent::Focused(true), 1.0), None);
+    }
+
+    /// Stands in for an application and counts how many are alive, since
+    /// only one of them can own the window's swapchain.
+    struct FakeApplication {
+        device_lost: bool,
+        restoration_data: Option<Vec<u8>>,
+        alive: Rc<Cell<usize>>,
+    }
+
+    impl FakeApplication {
+        fn new(alive: &Rc<Cell<usize>>, restoration_data: Option<Vec<u8>>) -> Self {
+            alive.set(alive.get() + 1);
+            Self {
+                device_lost: false,
+                restoration_data,
+                alive: alive.clone(),
+            }
+        }
+    }
+
+    impl Drop for FakeApplication {
+        fn drop(&mut self) {
+            self.alive.set(self.alive.get() - 1);
+        }
+    }
+
+    impl Restartable for FakeApplication {
+        fn is_device_lost(&self) -> bool {
+            self.device_lost
+        }
+
+        fn restoration_data(&self) -> Option<Vec<u8>> {
+            self.restoration_data.clone()
+        }
+    }
+
+    #[test]
+    fn device_loss_restarts_with_the_restoration_data() {
+        let alive = Rc::new(Cell::new(0));
+        let mut running = Some(FakeApplication::new(&alive, Some(vec![1, 2, 3])));
+        running.as_mut().unwrap().device_lost = true;
+        restart_if_device_lost(&mut running, |restoration_data| {
+            assert_eq!(alive.get(), 0, "The old application is still alive");
+            FakeApplication::new(&alive, restoration_data)
+        });
+        let app = running.as_ref().unwrap();
+        assert!(!app.device_lost);
+        assert_eq!(app.restoration_data, Some(vec![1, 2, 3]));
+        assert_eq!(alive.get(), 1);
+    }
+
+    #[test]
+    fn working_devices_keep_the_application() {
+        let alive = Rc::new(Cell::new(0));
+        let mut running = Some(FakeApplication::new(&alive, None));
+        restart_if_device_lost(&mut running, |_| unreachable!());
+        assert!(running.is_some());
+        assert_eq!(alive.get(), 1);
+    }
 }
@@ -0,0 +1,129 @@
+//! Measures sending pointer events to the engine one by one against sending
+//! them in a single batch, the way [`FlutterApplication::flush_pointer_events`]
+//! does once per event loop iteration.
+//!
+//! Runs offscreen like the golden tests, so it needs an X server as well.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::runtime::Runtime;
+use winit::{
+    dpi::PhysicalPosition,
+    event::{DeviceId, Event},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+};
+
+use crate::{
+    flutter_application::{FlutterApplication, FlutterApplicationCallback},
+    golden::{GoldenError, GoldenTest},
+};
+
+/// The best timings of all rounds, measured from sending the first event.
+#[derive(Debug, Clone, Copy)]
+pub struct PointerTimings {
+    /// Until the last event was handed to the engine.
+    pub sent: Duration,
+    /// Until the framework dispatched all of them.
+    pub dispatched: Duration,
+}
+
+impl PointerTimings {
+    fn min(self, other: Self) -> Self {
+        Self {
+            sent: self.sent.min(other.sent),
+            dispatched: self.dispatched.min(other.dispatched),
+        }
+    }
+}
+
+pub struct PointerBenchmarkResult {
+    pub one_by_one: PointerTimings,
+    pub batched: PointerTimings,
+}
+
+pub struct PointerBenchmark {
+    /// Boots the bundle, its timeout applies to every round.
+    pub test: GoldenTest,
+    /// Hover moves sent per round.
+    pub events: usize,
+    pub rounds: usize,
+}
+
+impl PointerBenchmark {
+    /// Can only be called once per process, see [`GoldenTest::start`].
+    pub fn run(&self, runtime: Arc<Runtime>) -> Result<PointerBenchmarkResult, GoldenError> {
+        let (mut event_loop, mut app) = self.test.start(runtime);
+        // Not an actual device, the events only need a consistent id.
+        let device_id = unsafe { DeviceId::dummy() };
+        let mut one_by_one: Option<PointerTimings> = None;
+        let mut batched: Option<PointerTimings> = None;
+        for _ in 0..self.rounds {
+            for (batch, best) in [(false, &mut one_by_one), (true, &mut batched)] {
+                // Hovering might have triggered frames, which would skew the
+                // next round.
+                self.test.wait_until_idle(&mut event_loop, &mut app)?;
+                let timings = self.round(&mut event_loop, &mut app, device_id, batch)?;
+                *best = Some(best.map_or(timings, |best| best.min(timings)));
+            }
+        }
+        let zero = PointerTimings {
+            sent: Duration::ZERO,
+            dispatched: Duration::ZERO,
+        };
+        Ok(PointerBenchmarkResult {
+            one_by_one: one_by_one.unwrap_or(zero),
+            batched: batched.unwrap_or(zero),
+        })
+    }
+
+    fn round(
+        &self,
+        event_loop: &mut EventLoop<FlutterApplicationCallback>,
+        app: &mut FlutterApplication,
+        device_id: DeviceId,
+        batch: bool,
+    ) -> Result<PointerTimings, GoldenError> {
+        let start = Instant::now();
+        for i in 0..self.events {
+            // Zig-zag along the top of the view, so every event moves.
+            let position =
+                PhysicalPosition::new((i % self.test.width as usize) as f64, (i % 2) as f64);
+            app.mouse_moved(device_id, position);
+            if !batch {
+                app.flush_pointer_events();
+            }
+        }
+        app.flush_pointer_events();
+        let sent = start.elapsed();
+
+        let dispatched = Arc::new(Mutex::new(None));
+        {
+            let dispatched = dispatched.clone();
+            app.after_ui_thread_idle(move |_| {
+                *dispatched.lock().unwrap() = Some(Instant::now());
+                true
+            });
+        }
+        let deadline = start + self.test.timeout;
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(deadline);
+            if let Event::UserEvent(handler) = event {
+                if handler(app) {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            if Instant::now() >= deadline {
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+        let dispatched = dispatched.lock().unwrap().ok_or(GoldenError::Timeout)?;
+        Ok(PointerTimings {
+            sent,
+            dispatched: dispatched - start,
+        })
+    }
+}